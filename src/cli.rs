@@ -1,6 +1,20 @@
-use std::path::PathBuf;
+use std::{
+    fmt,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
 
-use crate::{printer::PrintMode, utils};
+use crate::{
+    config::{Config, EffectiveSetting, Settings, Source},
+    fs::{Age, FileSize, IgnoreRules, MetadataFilters, PointInTime, SortKey, Threads},
+    git::GitSearch,
+    printer::{ColorSpecs, HyperlinkFormat, PrintMode, Theme, UserColorSpec},
+    rules::RuleSet,
+    search::Engine,
+    utils, GGError,
+};
 use clap::{ArgAction, Parser, Subcommand};
 
 #[derive(Parser, Debug)]
@@ -20,54 +34,297 @@ pub struct Cli {
     )]
     patterns: Vec<String>,
 
+    /// only report files in which every pattern matches somewhere
+    #[clap(long, default_value_t = false, conflicts_with = "files")]
+    pub all_of: bool,
+
+    /// exclude the files in which this pattern matches (can be repeated)
+    #[clap(long, value_name = "PATTERN", conflicts_with = "files")]
+    pub none_of: Vec<String>,
+
+    /// read patterns from a file, one per line, '-' reading them from stdin which then isn't
+    /// searched (can be repeated, empty lines and lines starting with '#' are ignored)
+    #[clap(long, value_name = "FILE", conflicts_with = "files")]
+    pub pattern_file: Vec<PathBuf>,
+
+    /// search for the rules of a TOML rules file instead of patterns, exiting with 1 when a rule
+    /// with the error severity matches and with 2 on errors
+    #[clap(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["patterns", "pattern_file", "files"]
+    )]
+    pub rules: Option<PathBuf>,
+
     /// path in which to search recursively
     #[arg(num_args = 0..)]
     pub paths: Vec<PathBuf>,
 
+    /// search exactly the files listed in FILE, separated by newlines or NUL bytes, instead of
+    /// walking directories ('-' reading the list from stdin, which then isn't searched)
+    #[clap(long, value_name = "FILE", conflicts_with = "paths")]
+    pub files_from: Option<PathBuf>,
+
+    /// search the tree of a git revision (commit, branch, tag...) without checking it out,
+    /// reporting paths as REV:path
+    #[clap(long, value_name = "REV", conflicts_with_all = ["staged", "changed", "files_from"])]
+    pub rev: Option<String>,
+
+    /// search the git index, i.e. the content staged for the next commit, reporting paths as
+    /// :path
+    #[clap(long, default_value_t = false, conflicts_with_all = ["changed", "files_from"])]
+    pub staged: bool,
+
+    /// only search the files that differ from HEAD, or from --since, untracked ones included
+    #[clap(long, default_value_t = false, conflicts_with = "files_from")]
+    pub changed: bool,
+
+    /// revision --changed compares the working tree against
+    #[clap(long, value_name = "REF", requires = "changed")]
+    pub since: Option<String>,
+
+    /// only search files modified within this duration, e.g. 30m, 2h, 2d or 1w
+    #[clap(long, value_name = "DURATION", conflicts_with_all = ["rev", "staged", "files_from"])]
+    pub changed_within: Option<Age>,
+
+    /// only search files modified before this UTC date (YYYY-MM-DD or YYYY-MM-DD HH:MM[:SS]),
+    /// or longer ago than a duration such as 2d
+    #[clap(long, value_name = "DATE", conflicts_with_all = ["rev", "staged", "files_from"])]
+    pub changed_before: Option<PointInTime>,
+
+    /// only search files of at least this size, e.g. 512, 10K, 5M or 1G
+    #[clap(long, value_name = "SIZE", conflicts_with_all = ["rev", "staged", "files_from"])]
+    pub min_size: Option<FileSize>,
+
+    /// skip files larger than this size, e.g. 512, 10K, 5M or 1G
+    #[clap(long, value_name = "SIZE", conflicts_with_all = ["rev", "staged", "files_from"])]
+    pub max_size: Option<FileSize>,
+
+    /// path to report the results of stdin under, e.g. the file of an unsaved editor buffer
+    /// piped to gg
+    #[clap(long, value_name = "NAME", alias = "stdin-label")]
+    pub label: Option<PathBuf>,
+
     /// paths to ignore when recursively walking target directory
     #[clap(short = 'I', long)]
     pub ignore_paths: Vec<PathBuf>,
 
-    /// disregard .gitignore rules when recursively walking directory (defaults to false)
-    #[clap(short = 'G', long, default_value_t = false)]
-    pub disregard_gitignore: bool,
+    /// disregard .gitignore and .git/info/exclude rules when recursively walking directory (defaults to false)
+    #[clap(
+        short = 'G',
+        long,
+        visible_alias = "disregard-gitignore",
+        default_value_t = false
+    )]
+    pub no_ignore_vcs: bool,
+
+    /// disregard the global gitignore file (defaults to false)
+    #[clap(long, default_value_t = false)]
+    pub no_ignore_global: bool,
+
+    /// disregard ignore files found in parent directories (defaults to false)
+    #[clap(long, default_value_t = false)]
+    pub no_ignore_parent: bool,
+
+    /// disregard .ignore and .ggignore files (defaults to false)
+    #[clap(long, default_value_t = false)]
+    pub no_ignore_dot: bool,
+
+    /// additional ignore file using gitignore syntax (can be repeated)
+    #[clap(long, value_name = "PATH")]
+    pub ignore_file: Vec<PathBuf>,
+
+    /// number of threads to use, or 'auto' to pick one based on the machine and the tree size
+    /// (defaults to auto)
+    #[clap(short = 'T', long)]
+    pub n_threads: Option<Threads>,
 
-    /// number of threads to use
-    #[clap(short = 'T', long, default_value_t = 4)]
-    pub n_threads: usize,
+    /// use PCRE2, which supports look-around and backreferences (same as --engine pcre2)
+    #[clap(short = 'P', long, default_value_t = false, conflicts_with = "engine")]
+    pub pcre2: bool,
+
+    /// regex engine: default, pcre2, or auto to fall back to PCRE2 for patterns the default
+    /// engine rejects (defaults to default)
+    #[clap(long, value_enum)]
+    pub engine: Option<Engine>,
 
     /// enable multiline matching
     #[clap(short = 'U', long, default_value_t = false)]
     pub multiline: bool,
 
+    /// let `.` match line terminators as well when matching across lines
+    #[clap(long, default_value_t = false, requires = "multiline")]
+    pub multiline_dotall: bool,
+
     /// output in JSON format
     #[clap(long, default_value_t = false)]
     pub json: bool,
 
+    /// output format (overrides --json and --file-paths-only)
+    #[clap(long, value_enum, value_name = "FORMAT")]
+    pub format: Option<PrintMode>,
+
     /// output file paths only
     #[clap(short = 'f', long, default_value_t = false)]
     pub file_paths_only: bool,
 
+    /// list the files that would be searched, without searching them
+    #[clap(long, default_value_t = false, conflicts_with = "patterns")]
+    pub files: bool,
+
+    /// sort results in ascending order (results are unsorted by default)
+    #[clap(long, value_name = "KEY", conflicts_with = "sortr")]
+    pub sort: Option<SortKey>,
+
+    /// sort results in descending order
+    #[clap(long, value_name = "KEY")]
+    pub sortr: Option<SortKey>,
+
+    /// print the file path above its matching lines (default)
+    #[clap(long, default_value_t = false, overrides_with = "no_heading")]
+    pub heading: bool,
+
+    /// print the file path on each matching line instead of above them
+    #[clap(long, default_value_t = false, overrides_with = "heading")]
+    pub no_heading: bool,
+
+    /// don't print line numbers
+    #[clap(short = 'n', long, default_value_t = false)]
+    pub no_line_number: bool,
+
+    /// follow file paths with a NUL byte instead of a newline or separator, e.g. for `xargs -0`
+    #[clap(short = '0', long, default_value_t = false)]
+    pub null: bool,
+
+    /// separator between the path, the line number and the line of a match
+    #[clap(long, value_name = "SEPARATOR", default_value = ":")]
+    pub field_match_separator: String,
+
+    /// omit lines longer than this many characters, 0 meaning no limit (when printing to a
+    /// terminal, defaults to previewing lines wider than the terminal)
+    #[clap(short = 'M', long, value_name = "NUM")]
+    pub max_columns: Option<usize>,
+
+    /// show the parts of long lines surrounding their matches instead of omitting them
+    #[clap(long, default_value_t = false)]
+    pub max_columns_preview: bool,
+
+    /// command used to page the output when printing to a terminal (defaults to $PAGER, or less)
+    #[clap(long, value_name = "COMMAND", conflicts_with = "no_pager")]
+    pub pager: Option<String>,
+
+    /// don't page the output
+    #[clap(long, default_value_t = false)]
+    pub no_pager: bool,
+
+    /// print statistics about the search once it's over
+    #[clap(long, default_value_t = false)]
+    pub stats: bool,
+
     /// output absolute paths (defaults to relative)
-    #[clap(short = 'A', long, default_value_t = false)]
+    #[clap(
+        short = 'A',
+        long,
+        default_value_t = false,
+        overrides_with = "relative_paths"
+    )]
     pub absolute_paths: bool,
 
+    /// output relative paths, e.g. when the configuration asks for absolute ones
+    #[clap(long, default_value_t = false, overrides_with = "absolute_paths")]
+    pub relative_paths: bool,
+
     /// disable colored output (colored by default)
-    #[clap(short = 'C', long, default_value_t = false)]
+    #[clap(
+        short = 'C',
+        long,
+        default_value_t = false,
+        overrides_with = "colored_output"
+    )]
     pub disable_colored_output: bool,
 
+    /// color the output, e.g. when the configuration disables colors
+    #[clap(
+        long,
+        default_value_t = false,
+        overrides_with = "disable_colored_output"
+    )]
+    pub colored_output: bool,
+
+    /// color theme (defaults to default)
+    #[clap(long, value_enum)]
+    pub theme: Option<Theme>,
+
+    /// color spec such as 'path:fg:blue', 'match:style:nobold' or 'line:none', applied on top of
+    /// the theme and of the specs found in GG_COLORS (can be repeated)
+    #[clap(long, value_name = "SPEC")]
+    pub colors: Vec<UserColorSpec>,
+
+    /// highlight the matches of each pattern in a different color when searching for several
+    #[clap(long, default_value_t = false)]
+    pub pattern_colors: bool,
+
+    /// print results as soon as they are found, JSON still holding one object per file (always the
+    /// case when searching piped stdin)
+    #[clap(long, default_value_t = false)]
+    pub line_buffered: bool,
+
     /// filter on filetype (defaults to all filetypes)
-    #[clap(short = 't', long)]
+    #[clap(short = 't', long, global = true)]
     pub filter_filetypes: Vec<String>,
 
+    /// exclude a filetype from the search, e.g. -N js (can be repeated)
+    #[clap(short = 'N', long = "type-not", value_name = "TYPE", global = true)]
+    pub exclude_filetypes: Vec<String>,
+
+    /// add a filetype definition, e.g. --type-add 'proto:*.proto', which `gg types` lists too
+    /// (can be repeated)
+    #[clap(long = "type-add", value_name = "TYPE:GLOB", global = true)]
+    pub add_filetypes: Vec<String>,
+
+    /// clear the globs of a filetype, e.g. before redefining it with --type-add
+    #[clap(long = "type-clear", value_name = "TYPE", global = true)]
+    pub clear_filetypes: Vec<String>,
+
     /// disable hyperlinks in output (defaults to false)
-    #[clap(short = 'H', long, default_value_t = false)]
+    #[clap(
+        short = 'H',
+        long,
+        default_value_t = false,
+        overrides_with = "hyperlinks"
+    )]
     pub disable_hyperlinks: bool,
 
+    /// enable hyperlinks in output, e.g. when the configuration disables them
+    #[clap(long, default_value_t = false, overrides_with = "disable_hyperlinks")]
+    pub hyperlinks: bool,
+
+    /// hyperlink format: one of default, file, vscode, cursor, kitty, wezterm, idea, or a template
+    /// using {path}, {line}, {column} and {host}, e.g. 'vscode://file{path}:{line}:{column}'
+    #[clap(long, value_name = "FORMAT")]
+    pub hyperlink_format: Option<HyperlinkFormat>,
+
     /// enable devicons in output (defaults to false)
-    #[clap(short = 'D', long, default_value_t = false)]
+    #[clap(
+        short = 'D',
+        long,
+        default_value_t = false,
+        overrides_with = "no_devicons"
+    )]
     pub enable_devicons: bool,
 
+    /// disable devicons in output, e.g. when the configuration enables them
+    #[clap(long, default_value_t = false, overrides_with = "enable_devicons")]
+    pub no_devicons: bool,
+
+    /// configuration profile to use, as defined in the configuration files
+    #[clap(long, value_name = "NAME", conflicts_with = "no_config")]
+    pub profile: Option<String>,
+
+    /// ignore the configuration files
+    #[clap(long, default_value_t = false)]
+    pub no_config: bool,
+
     /// Subcommands
     #[clap(subcommand)]
     pub sub_command: Option<Commands>,
@@ -81,30 +338,78 @@ pub enum Commands {
         #[arg(short, long, default_value_t = false)]
         force: bool,
     },
+    /// List all known filetypes and their globs, or the ones selected with -t and -N
+    Types,
+    /// Show the number of threads `-T auto` picks for the given paths, and why
+    Threads {
+        /// paths in which the search would happen
+        #[arg(num_args = 0..)]
+        paths: Vec<PathBuf>,
+    },
+    /// Find the commits adding or removing lines that match a pattern, like `git log -G`
+    Log {
+        /// a regex pattern to search for in the lines changed by commits
+        pattern: String,
+        /// only look at the changes made to these paths
+        #[arg(num_args = 0..)]
+        paths: Vec<PathBuf>,
+        /// stop after this many commits
+        #[arg(short = 'n', long, value_name = "NUM")]
+        max_count: Option<usize>,
+    },
+    /// Inspect the configuration files
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    /// Print the effective settings and where they come from
+    Show,
 }
 
 const DEFAULT_PATH: &str = ".";
 
+/// Pattern file standing for stdin.
+const STDIN_PATH: &str = "-";
+/// Lines of pattern files starting with this are comments, `\#` matching a literal `#` instead.
+const PATTERN_FILE_COMMENT: char = '#';
+
+const PAGER_ENV_VAR: &str = "PAGER";
+const DEFAULT_PAGER: &str = "less";
+
+/// Environment variable holding `;` separated color specs, e.g. `path:fg:blue;line:none`.
+const COLORS_ENV_VAR: &str = "GG_COLORS";
+
 impl Cli {
     pub fn validate(&mut self) {
         if self.sub_command.is_some() {
             return;
         }
-        if self.patterns.is_empty() {
-            // If no patterns are provided using -e, the positional argument should be treated as a
-            // pattern
+        if self.files {
+            // When listing files, there is no pattern and every positional argument is a path
+            if let Some(pattern) = self.pattern.take() {
+                self.paths.insert(0, PathBuf::from(pattern));
+            }
+            return;
+        }
+        if self.patterns.is_empty() && self.pattern_file.is_empty() && self.rules.is_none() {
+            // If no patterns are provided using -e, --pattern-file or --rules, the positional
+            // argument should be treated as a pattern
             if self.pattern.is_none() {
                 eprintln!("error: the following required arguments were not provided: <PATTERN>");
                 std::process::exit(1);
             }
         } else if self.pattern.is_some() {
-            // If patterns are provided using -e and we have what seems to be an additional positional pattern,
+            // If patterns are provided using -e, --pattern-file or --rules and we have what seems to be an additional positional pattern,
             // it should be interpreted as a path.
             self.paths
                 .push(PathBuf::from(self.pattern.clone().unwrap()));
             self.pattern = None;
         } else {
-            // If patterns are provided using -e and no positional arguments are provided, use
+            // If patterns are provided using -e, --pattern-file or --rules and no positional arguments are provided, use
             // default path
             self.paths = vec![PathBuf::from(DEFAULT_PATH)];
         }
@@ -114,17 +419,53 @@ impl Cli {
 #[derive(Debug)]
 pub struct PostProcessedCli {
     pub patterns: Vec<String>,
+    /// patterns or files to search were read from stdin, which therefore can't be searched
+    pub stdin_consumed: bool,
+    pub all_of: bool,
+    pub none_of: Vec<String>,
+    pub rules: Option<Arc<RuleSet>>,
     pub paths: Vec<PathBuf>,
+    /// files searched instead of walking `paths`
+    pub files_from: Option<Vec<PathBuf>>,
+    /// path the contents of stdin come from
+    pub stdin_label: Option<PathBuf>,
+    pub git: Option<GitSearch>,
     pub ignored_paths: Vec<PathBuf>,
-    pub n_threads: usize,
-    pub disregard_gitignore: bool,
+    /// size and modification time conditions on the files walked
+    pub metadata_filters: MetadataFilters,
+    pub n_threads: Threads,
+    pub ignore_rules: IgnoreRules,
     pub multiline: bool,
+    pub multiline_dotall: bool,
+    pub engine: Engine,
     pub print_mode: PrintMode,
+    pub heading: bool,
+    pub line_numbers: bool,
+    pub null_terminated_paths: bool,
+    pub field_separator: String,
+    pub max_columns: Option<usize>,
+    pub max_columns_preview: bool,
+    /// width lines are fit into, along with what precedes them, when no maximum number of columns
+    /// is given
+    pub terminal_width: Option<usize>,
+    pub pager: Option<String>,
+    pub stats: bool,
+    pub list_files: bool,
+    pub sort: Option<SortKey>,
+    pub sort_reverse: bool,
     pub absolute_paths: bool,
     pub colored_output: bool,
+    pub color_specs: ColorSpecs,
+    pub pattern_colors: bool,
+    pub line_buffered: bool,
     pub filter_filetypes: Vec<String>,
+    pub exclude_filetypes: Vec<String>,
+    pub add_filetypes: Vec<String>,
+    pub clear_filetypes: Vec<String>,
     pub disable_hyperlinks: bool,
+    pub hyperlink_format: HyperlinkFormat,
     pub enable_devicons: bool,
+    pub effective_settings: Vec<EffectiveSetting>,
     pub sub_command: Option<Commands>,
 }
 
@@ -132,58 +473,449 @@ impl Default for PostProcessedCli {
     fn default() -> Self {
         PostProcessedCli {
             patterns: Vec::new(),
+            stdin_consumed: false,
+            all_of: false,
+            none_of: Vec::new(),
+            rules: None,
             paths: Vec::new(),
+            files_from: None,
+            stdin_label: None,
+            git: None,
             ignored_paths: Vec::new(),
-            n_threads: 1,
-            disregard_gitignore: false,
+            metadata_filters: MetadataFilters::default(),
+            n_threads: Threads::Auto,
+            ignore_rules: IgnoreRules::default(),
             multiline: false,
+            multiline_dotall: false,
+            engine: Engine::Default,
             print_mode: PrintMode::Text,
+            heading: true,
+            line_numbers: true,
+            null_terminated_paths: false,
+            field_separator: String::from(":"),
+            max_columns: None,
+            max_columns_preview: false,
+            terminal_width: None,
+            pager: None,
+            stats: false,
+            list_files: false,
+            sort: None,
+            sort_reverse: false,
             absolute_paths: false,
             colored_output: true,
+            color_specs: ColorSpecs::default(),
+            pattern_colors: false,
+            line_buffered: false,
             filter_filetypes: Vec::new(),
+            exclude_filetypes: Vec::new(),
+            add_filetypes: Vec::new(),
+            clear_filetypes: Vec::new(),
             disable_hyperlinks: false,
+            hyperlink_format: HyperlinkFormat::default(),
             enable_devicons: false,
+            effective_settings: Vec::new(),
             sub_command: None,
         }
     }
 }
 
-pub fn process_cli_args(mut cli: Cli) -> PostProcessedCli {
+pub fn process_cli_args(mut cli: Cli) -> Result<PostProcessedCli, GGError> {
     cli.validate();
 
     if cli.paths.is_empty() {
         cli.paths.push(PathBuf::from(DEFAULT_PATH));
     }
 
-    if let Some(Commands::Upgrade { force }) = cli.sub_command {
-        return PostProcessedCli {
-            sub_command: Some(Commands::Upgrade { force }),
+    let ignore_rules = ignore_rules(&cli);
+    let mut config_command = None;
+    let mut log_command = None;
+    match cli.sub_command.take() {
+        Some(Commands::Upgrade { force }) => {
+            return Ok(PostProcessedCli {
+                sub_command: Some(Commands::Upgrade { force }),
+                ..Default::default()
+            });
+        }
+        Some(Commands::Types) => {
+            return Ok(PostProcessedCli {
+                filter_filetypes: cli.filter_filetypes,
+                exclude_filetypes: cli.exclude_filetypes,
+                add_filetypes: cli.add_filetypes,
+                clear_filetypes: cli.clear_filetypes,
+                sub_command: Some(Commands::Types),
+                ..Default::default()
+            });
+        }
+        Some(Commands::Threads { paths }) => {
+            return Ok(PostProcessedCli {
+                paths: utils::resolve_paths(if paths.is_empty() {
+                    vec![PathBuf::from(DEFAULT_PATH)]
+                } else {
+                    paths
+                }),
+                ignored_paths: utils::resolve_paths(cli.ignore_paths),
+                ignore_rules,
+                filter_filetypes: cli.filter_filetypes,
+                exclude_filetypes: cli.exclude_filetypes,
+                add_filetypes: cli.add_filetypes,
+                clear_filetypes: cli.clear_filetypes,
+                sub_command: Some(Commands::Threads { paths: Vec::new() }),
+                ..Default::default()
+            });
+        }
+        Some(Commands::Config { command }) => config_command = Some(command),
+        Some(Commands::Log {
+            pattern,
+            paths,
+            max_count,
+        }) => {
+            // searched for like any other pattern, the output options applying as usual
+            cli.patterns = vec![pattern.clone()];
+            log_command = Some(Commands::Log {
+                pattern,
+                paths,
+                max_count,
+            });
+        }
+        None => {}
+    }
+
+    let config = if cli.no_config {
+        Config::default()
+    } else {
+        Config::load(&std::env::current_dir()?, cli.profile.as_deref())?
+    };
+    let mut settings = SettingsResolver::new(&config);
+    let n_threads = settings.resolve("threads", cli.n_threads, |s| s.threads, Threads::Auto);
+    let enable_devicons = settings.resolve(
+        "devicons",
+        flag_pair(cli.enable_devicons, cli.no_devicons),
+        |s| s.devicons,
+        false,
+    );
+    let enable_hyperlinks = settings.resolve(
+        "hyperlinks",
+        flag_pair(cli.hyperlinks, cli.disable_hyperlinks),
+        |s| s.hyperlinks,
+        true,
+    );
+    let hyperlink_format = settings.resolve(
+        "hyperlink-format",
+        cli.hyperlink_format.take(),
+        |s| s.hyperlink_format.clone(),
+        HyperlinkFormat::default(),
+    );
+    let colored_output = settings.resolve(
+        "colored-output",
+        flag_pair(cli.colored_output, cli.disable_colored_output),
+        |s| s.colored_output,
+        true,
+    );
+    let absolute_paths = settings.resolve(
+        "absolute-paths",
+        flag_pair(cli.absolute_paths, cli.relative_paths),
+        |s| s.absolute_paths,
+        false,
+    );
+    let theme = settings.resolve("theme", cli.theme, |s| s.theme, Theme::Default);
+    let color_specs = settings.resolve_color_specs(theme, &cli.colors)?;
+
+    let (max_columns, max_columns_preview, terminal_width) = match cli.max_columns {
+        Some(0) => (None, false, None),
+        Some(max_columns) => (Some(max_columns), cli.max_columns_preview, None),
+        None => (None, true, utils::terminal_width()),
+    };
+
+    if let Some(command) = config_command {
+        return Ok(PostProcessedCli {
+            effective_settings: settings.effective,
+            sub_command: Some(Commands::Config { command }),
             ..Default::default()
-        };
+        });
     }
-    PostProcessedCli {
-        patterns: if cli.patterns.is_empty() {
-            vec![cli.pattern.unwrap()]
+
+    let rules = cli
+        .rules
+        .as_deref()
+        .map(RuleSet::load)
+        .transpose()?
+        .map(Arc::new);
+
+    let stdin_readers = cli
+        .pattern_file
+        .iter()
+        .filter(|path| *path == STDIN_PATH)
+        .count()
+        + usize::from(cli.files_from.as_deref() == Some(Path::new(STDIN_PATH)));
+    // whichever reads stdin second would get nothing
+    if stdin_readers > 1 {
+        return Err(GGError::StdinReadTwice);
+    }
+    let stdin_consumed = stdin_readers > 0;
+    let patterns = if cli.files {
+        Vec::new()
+    } else if let Some(rules) = &rules {
+        rules.patterns()
+    } else if cli.patterns.is_empty() && cli.pattern_file.is_empty() {
+        vec![cli.pattern.unwrap()]
+    } else {
+        let mut patterns = cli.patterns;
+        for path in &cli.pattern_file {
+            patterns.extend(read_pattern_file(path)?);
+        }
+        // no patterns would match nothing, most likely not what was meant
+        if patterns.is_empty() {
+            return Err(GGError::EmptyPatternFiles);
+        }
+        patterns
+    };
+
+    let files_from = cli.files_from.as_deref().map(read_file_list).transpose()?;
+    let cwd = std::env::current_dir()?;
+    let now = SystemTime::now();
+
+    Ok(PostProcessedCli {
+        patterns,
+        stdin_consumed,
+        all_of: cli.all_of,
+        none_of: cli.none_of,
+        rules,
+        paths: utils::resolve_paths(cli.paths),
+        files_from,
+        stdin_label: cli.label.map(|label| cwd.join(label)),
+        git: if let Some(rev) = cli.rev {
+            Some(GitSearch::Rev(rev))
+        } else if cli.staged {
+            Some(GitSearch::Staged)
+        } else if cli.changed {
+            Some(GitSearch::Changed { since: cli.since })
         } else {
-            cli.patterns
+            None
         },
-        paths: utils::resolve_paths(cli.paths),
         ignored_paths: utils::resolve_paths(cli.ignore_paths),
-        n_threads: cli.n_threads,
-        disregard_gitignore: cli.disregard_gitignore,
+        metadata_filters: MetadataFilters {
+            modified_after: cli
+                .changed_within
+                .map(|Age(age)| PointInTime::Ago(age).resolve(now)),
+            modified_before: cli.changed_before.map(|time| time.resolve(now)),
+            min_size: cli.min_size.map(|FileSize(size)| size),
+            max_size: cli.max_size.map(|FileSize(size)| size),
+        },
+        n_threads,
+        ignore_rules,
         multiline: cli.multiline,
-        print_mode: if cli.json {
+        multiline_dotall: cli.multiline_dotall,
+        engine: if cli.pcre2 {
+            Engine::Pcre2
+        } else {
+            cli.engine.unwrap_or_default()
+        },
+        print_mode: if let Some(format) = cli.format {
+            format
+        } else if cli.json {
             PrintMode::Json
-        } else if cli.file_paths_only {
+        } else if cli.file_paths_only || cli.files {
             PrintMode::Files
         } else {
             PrintMode::Text
         },
-        absolute_paths: cli.absolute_paths,
-        colored_output: !cli.disable_colored_output,
+        heading: !cli.no_heading,
+        line_numbers: !cli.no_line_number,
+        null_terminated_paths: cli.null,
+        field_separator: cli.field_match_separator,
+        max_columns,
+        max_columns_preview,
+        terminal_width,
+        pager: if cli.no_pager {
+            None
+        } else {
+            cli.pager
+                .or_else(|| std::env::var(PAGER_ENV_VAR).ok())
+                .or_else(|| Some(String::from(DEFAULT_PAGER)))
+                .filter(|pager| !pager.trim().is_empty())
+        },
+        stats: cli.stats,
+        list_files: cli.files,
+        sort: cli.sort.or(cli.sortr),
+        sort_reverse: cli.sortr.is_some(),
+        absolute_paths,
+        colored_output,
+        color_specs,
+        pattern_colors: cli.pattern_colors,
+        line_buffered: cli.line_buffered,
         filter_filetypes: cli.filter_filetypes,
-        disable_hyperlinks: cli.disable_hyperlinks,
-        enable_devicons: cli.enable_devicons,
-        sub_command: cli.sub_command,
+        exclude_filetypes: cli.exclude_filetypes,
+        add_filetypes: cli.add_filetypes,
+        clear_filetypes: cli.clear_filetypes,
+        disable_hyperlinks: !enable_hyperlinks,
+        hyperlink_format,
+        enable_devicons,
+        effective_settings: settings.effective,
+        sub_command: log_command,
+    })
+}
+
+/// Value of a setting given by a pair of opposite flags, if either of them is set. Flags of a pair
+/// override each other, so at most one of them is.
+fn flag_pair(enable: bool, disable: bool) -> Option<bool> {
+    if enable {
+        Some(true)
+    } else if disable {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Read the patterns of a pattern file, `-` standing for stdin.
+fn read_pattern_file(path: &Path) -> Result<Vec<String>, GGError> {
+    let contents = if path == STDIN_PATH {
+        let mut contents = String::new();
+        io::stdin().read_to_string(&mut contents).map(|_| contents)
+    } else {
+        std::fs::read_to_string(path)
+    }
+    .map_err(|source| GGError::PatternFile {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    Ok(contents
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with(PATTERN_FILE_COMMENT))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Read the paths listed in a file, `-` standing for stdin. Paths are separated by NUL bytes if
+/// there are any, as output by `fd -0` or `git diff --name-only -z`, and by newlines otherwise.
+fn read_file_list(path: &Path) -> Result<Vec<PathBuf>, GGError> {
+    let contents = if path == STDIN_PATH {
+        let mut contents = Vec::new();
+        io::stdin().read_to_end(&mut contents).map(|_| contents)
+    } else {
+        std::fs::read(path)
+    }
+    .map_err(|source| GGError::FileList {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let separator = if contents.contains(&b'\0') {
+        b'\0'
+    } else {
+        b'\n'
+    };
+    let cwd = std::env::current_dir()?;
+    Ok(contents
+        .split(|&byte| byte == separator)
+        .map(|path| path.strip_suffix(b"\r").unwrap_or(path))
+        .filter(|path| !path.is_empty())
+        .map(|path| cwd.join(String::from_utf8_lossy(path).as_ref()))
+        .collect())
+}
+
+fn ignore_rules(cli: &Cli) -> IgnoreRules {
+    IgnoreRules {
+        vcs: !cli.no_ignore_vcs,
+        global: !cli.no_ignore_global,
+        parents: !cli.no_ignore_parent,
+        dot: !cli.no_ignore_dot,
+        ignore_files: cli.ignore_file.clone(),
+    }
+}
+
+/// Resolves settings from the command line, the configuration files and the defaults, in that
+/// order of precedence, while keeping track of where each effective value comes from.
+struct SettingsResolver<'a> {
+    config: &'a Config,
+    effective: Vec<EffectiveSetting>,
+}
+
+impl<'a> SettingsResolver<'a> {
+    fn new(config: &'a Config) -> SettingsResolver<'a> {
+        SettingsResolver {
+            config,
+            effective: Vec::new(),
+        }
+    }
+
+    fn resolve<T: fmt::Display>(
+        &mut self,
+        name: &'static str,
+        cli_value: Option<T>,
+        setting: impl Fn(&Settings) -> Option<T>,
+        default: T,
+    ) -> T {
+        let (value, source) = match cli_value {
+            Some(value) => (value, Source::CommandLine),
+            None => self
+                .config
+                .get(setting)
+                .unwrap_or((default, Source::Default)),
+        };
+        self.effective.push(EffectiveSetting {
+            name,
+            value: value.to_string(),
+            source,
+        });
+        value
+    }
+
+    /// Color specs are layered rather than overridden: the configuration's specs are applied on
+    /// top of the theme, then the ones found in GG_COLORS, then the ones from the command line.
+    fn resolve_color_specs(
+        &mut self,
+        theme: Theme,
+        cli_specs: &[UserColorSpec],
+    ) -> Result<ColorSpecs, GGError> {
+        let mut color_specs = ColorSpecs::from_theme(theme);
+        if let Some((specs, source)) = self.config.get(|s| s.colors.clone()) {
+            for spec in &specs {
+                let spec = spec.parse::<UserColorSpec>().map_err(|message| {
+                    let Source::File { path, .. } = source.clone() else {
+                        unreachable!("configuration settings always come from a file");
+                    };
+                    GGError::Config { path, message }
+                })?;
+                color_specs.apply(&spec);
+            }
+            self.effective.push(EffectiveSetting {
+                name: "colors",
+                value: specs.join(";"),
+                source,
+            });
+        }
+        if let Ok(env_specs) = std::env::var(COLORS_ENV_VAR) {
+            // an invalid environment shouldn't prevent searching
+            for spec in env_specs
+                .split(';')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+            {
+                match spec.parse::<UserColorSpec>() {
+                    Ok(spec) => color_specs.apply(&spec),
+                    Err(err) => eprintln!("warning: ignoring {COLORS_ENV_VAR} entry: {err}"),
+                }
+            }
+            self.effective.push(EffectiveSetting {
+                name: "colors",
+                value: env_specs,
+                source: Source::Environment(COLORS_ENV_VAR),
+            });
+        }
+        for spec in cli_specs {
+            color_specs.apply(spec);
+        }
+        if !cli_specs.is_empty() {
+            self.effective.push(EffectiveSetting {
+                name: "colors",
+                value: cli_specs
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(";"),
+                source: Source::CommandLine,
+            });
+        }
+        Ok(color_specs)
     }
 }
@@ -0,0 +1,192 @@
+use std::{
+    collections::HashMap,
+    env, fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    fs::Threads,
+    printer::{HyperlinkFormat, Theme},
+    GGError,
+};
+
+/// Name of the per-repository configuration file, looked up from the current directory upwards.
+pub const REPO_CONFIG_FILENAME: &str = ".gg.toml";
+
+const GLOBAL_CONFIG_DIR: &str = "grip-grab";
+const GLOBAL_CONFIG_FILENAME: &str = "config.toml";
+
+/// Settings that can be persisted in a configuration file or in one of its profiles.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Settings {
+    #[serde(deserialize_with = "deserialize_threads")]
+    pub threads: Option<Threads>,
+    pub devicons: Option<bool>,
+    pub hyperlinks: Option<bool>,
+    pub hyperlink_format: Option<HyperlinkFormat>,
+    pub colored_output: Option<bool>,
+    pub absolute_paths: Option<bool>,
+    pub theme: Option<Theme>,
+    pub colors: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default)]
+struct ConfigFile {
+    settings: Settings,
+    profiles: HashMap<String, Settings>,
+}
+
+impl ConfigFile {
+    fn read(path: &Path) -> Result<ConfigFile, GGError> {
+        let invalid_config = |err: toml::de::Error| GGError::Config {
+            path: path.to_path_buf(),
+            message: err.message().to_string(),
+        };
+        let mut table: toml::Table =
+            toml::from_str(&std::fs::read_to_string(path)?).map_err(invalid_config)?;
+        let profiles = table
+            .remove("profiles")
+            .map(toml::Value::try_into)
+            .transpose()
+            .map_err(invalid_config)?
+            .unwrap_or_default();
+        let settings = toml::Value::Table(table)
+            .try_into()
+            .map_err(invalid_config)?;
+        Ok(ConfigFile { settings, profiles })
+    }
+}
+
+fn deserialize_threads<'de, D>(deserializer: D) -> Result<Option<Threads>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawThreads {
+        Count(usize),
+        Name(String),
+    }
+
+    let threads = match RawThreads::deserialize(deserializer)? {
+        RawThreads::Count(n) => n.to_string().parse(),
+        RawThreads::Name(name) => name.parse(),
+    };
+    threads.map(Some).map_err(serde::de::Error::custom)
+}
+
+/// Where an effective setting comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File {
+        path: PathBuf,
+        profile: Option<String>,
+    },
+    CommandLine,
+    Environment(&'static str),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File {
+                path,
+                profile: None,
+            } => write!(f, "{}", path.display()),
+            Source::File {
+                path,
+                profile: Some(profile),
+            } => write!(f, "{} (profile '{profile}')", path.display()),
+            Source::CommandLine => write!(f, "command line"),
+            Source::Environment(var) => write!(f, "environment ({var})"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Layer {
+    source: Source,
+    settings: Settings,
+}
+
+/// Configuration layers, from lowest to highest precedence: the global configuration file, its
+/// selected profile, the repository configuration file and its selected profile.
+#[derive(Debug, Default)]
+pub struct Config {
+    layers: Vec<Layer>,
+}
+
+impl Config {
+    pub fn load(cwd: &Path, profile: Option<&str>) -> Result<Config, GGError> {
+        let mut layers = Vec::new();
+        let mut profile_found = false;
+        for path in [global_config_path(), repo_config_path(cwd)]
+            .into_iter()
+            .flatten()
+        {
+            let mut file = ConfigFile::read(&path)?;
+            let profile_settings = profile.and_then(|name| file.profiles.remove(name));
+            layers.push(Layer {
+                source: Source::File {
+                    path: path.clone(),
+                    profile: None,
+                },
+                settings: file.settings,
+            });
+            if let Some(settings) = profile_settings {
+                profile_found = true;
+                layers.push(Layer {
+                    source: Source::File {
+                        path,
+                        profile: profile.map(str::to_string),
+                    },
+                    settings,
+                });
+            }
+        }
+        if let Some(name) = profile {
+            if !profile_found {
+                return Err(GGError::UnknownProfile(name.to_string()));
+            }
+        }
+        Ok(Config { layers })
+    }
+
+    /// Returns the value of a setting from the layer with the highest precedence defining it.
+    pub fn get<T>(&self, setting: impl Fn(&Settings) -> Option<T>) -> Option<(T, Source)> {
+        self.layers
+            .iter()
+            .rev()
+            .find_map(|layer| setting(&layer.settings).map(|value| (value, layer.source.clone())))
+    }
+}
+
+fn global_config_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    let path = config_dir
+        .join(GLOBAL_CONFIG_DIR)
+        .join(GLOBAL_CONFIG_FILENAME);
+    path.is_file().then_some(path)
+}
+
+fn repo_config_path(cwd: &Path) -> Option<PathBuf> {
+    cwd.ancestors()
+        .map(|dir| dir.join(REPO_CONFIG_FILENAME))
+        .find(|path| path.is_file())
+}
+
+/// A setting as resolved from the command line, the configuration files and the defaults.
+#[derive(Debug, Clone)]
+pub struct EffectiveSetting {
+    pub name: &'static str,
+    pub value: String,
+    pub source: Source,
+}
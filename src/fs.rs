@@ -1,14 +1,57 @@
-use ignore::{types::TypesBuilder, Error, WalkBuilder};
+use ignore::{
+    types::{FileTypeDef, Types, TypesBuilder},
+    Error, WalkBuilder,
+};
 
-use std::path::{Path, PathBuf};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{git::ChangedFiles, search::FileResults, stats::Stats, utils, GGError};
+
+/// Name of the project-level ignore file specific to gg.
+pub const GG_IGNORE_FILENAME: &str = ".ggignore";
+
+/// Which ignore rules are honored when walking the haystack.
+#[derive(Debug, Clone)]
+pub struct IgnoreRules {
+    /// `.gitignore` and `.git/info/exclude` files
+    pub vcs: bool,
+    /// the global gitignore file (`core.excludesFile`)
+    pub global: bool,
+    /// ignore files found in parent directories of the searched paths
+    pub parents: bool,
+    /// `.ignore` and `.ggignore` files
+    pub dot: bool,
+    /// additional ignore files given explicitly
+    pub ignore_files: Vec<PathBuf>,
+}
+
+impl Default for IgnoreRules {
+    fn default() -> IgnoreRules {
+        IgnoreRules {
+            vcs: true,
+            global: true,
+            parents: true,
+            dot: true,
+            ignore_files: Vec::new(),
+        }
+    }
+}
 
 pub fn walk_builder(
     paths: Vec<&Path>,
     ignored_paths: &[PathBuf],
-    n_threads: usize,
-    respect_gitignore: bool,
-    filter_filetypes: &[String],
-) -> WalkBuilder {
+    ignore_rules: &IgnoreRules,
+    types: Types,
+    changed_files: Option<Arc<ChangedFiles>>,
+    metadata_filters: MetadataFilters,
+    stats: Option<Arc<Stats>>,
+) -> Result<WalkBuilder, GGError> {
     let mut builder = WalkBuilder::new(paths[0]);
     // add all paths to the builder
     paths.iter().skip(1).for_each(|path| {
@@ -16,36 +59,514 @@ pub fn walk_builder(
     });
 
     // ft-based filtering
-    let mut types_builder = TypesBuilder::new();
-    types_builder.add_defaults();
-    add_custom_filetypes(&mut types_builder).unwrap();
-    for ft in filter_filetypes {
-        types_builder.select(ft);
-    }
-    builder.types(types_builder.build().unwrap());
+    builder.types(types);
 
     // path-based filtering
     let ignored_paths = ignored_paths.to_vec();
     builder.filter_entry(move |entry| {
+        if changed_files
+            .as_ref()
+            .is_some_and(|changed_files| !changed_files.contains(entry.path()))
+        {
+            return false;
+        }
         for ignore in &ignored_paths {
             if entry.path() == ignore {
+                if let Some(stats) = &stats {
+                    stats.add_filtered();
+                }
                 return false;
             }
         }
+        // directories are walked whatever their metadata, only the files in them being filtered
+        if !metadata_filters.is_empty()
+            && !entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_dir())
+            && !entry
+                .metadata()
+                .is_ok_and(|metadata| metadata_filters.matches(&metadata))
+        {
+            if let Some(stats) = &stats {
+                stats.add_filtered();
+            }
+            return false;
+        }
         true
     });
 
-    // .gitignore filtering
-    builder.git_ignore(respect_gitignore);
-
-    builder.threads(n_threads);
+    // ignore files filtering
     builder
+        .git_ignore(ignore_rules.vcs)
+        .git_exclude(ignore_rules.vcs)
+        .git_global(ignore_rules.vcs && ignore_rules.global)
+        .parents(ignore_rules.parents)
+        .ignore(ignore_rules.dot);
+    if ignore_rules.dot {
+        builder.add_custom_ignore_filename(GG_IGNORE_FILENAME);
+    }
+    for ignore_file in &ignore_rules.ignore_files {
+        if let Some(err) = builder.add_ignore(ignore_file) {
+            return Err(GGError::Ignore(err));
+        }
+    }
+
+    Ok(builder)
+}
+
+/// Whether a file would be searched by a walk of `paths`, ignore files aside.
+pub fn in_walk_scope(
+    path: &Path,
+    paths: &[PathBuf],
+    ignored_paths: &[PathBuf],
+    types: &Types,
+) -> bool {
+    paths.iter().any(|root| path.starts_with(root))
+        && !ignored_paths
+            .iter()
+            .any(|ignored| path.starts_with(ignored))
+        && !types.matched(path, false).is_ignore()
+}
+
+/// Conditions on the size and modification time of the files searched.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MetadataFilters {
+    /// only files modified at or after this time
+    pub modified_after: Option<SystemTime>,
+    /// only files modified before this time
+    pub modified_before: Option<SystemTime>,
+    /// only files of at least this many bytes
+    pub min_size: Option<u64>,
+    /// only files of at most this many bytes
+    pub max_size: Option<u64>,
+}
+
+impl MetadataFilters {
+    pub fn is_empty(&self) -> bool {
+        self.modified_after.is_none()
+            && self.modified_before.is_none()
+            && self.min_size.is_none()
+            && self.max_size.is_none()
+    }
+
+    /// Whether a file passes the filters, files whose modification time is unknown failing the
+    /// time conditions.
+    pub fn matches(&self, metadata: &std::fs::Metadata) -> bool {
+        let size = metadata.len();
+        if self.min_size.is_some_and(|min| size < min)
+            || self.max_size.is_some_and(|max| size > max)
+        {
+            return false;
+        }
+        if self.modified_after.is_none() && self.modified_before.is_none() {
+            return true;
+        }
+        metadata.modified().is_ok_and(|modified| {
+            self.modified_after.is_none_or(|after| modified >= after)
+                && self.modified_before.is_none_or(|before| modified < before)
+        })
+    }
+}
+
+/// A size in bytes, e.g. `512`, `10K`, `5M` or `1G` (powers of 1024).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileSize(pub u64);
+
+impl FromStr for FileSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FileSize, String> {
+        let invalid = || format!("invalid size '{s}' (expected e.g. 512, 10K, 5M or 1G)");
+        let (digits, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+        let multiplier: u64 = match unit.to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "k" | "kb" => 1 << 10,
+            "m" | "mb" => 1 << 20,
+            "g" | "gb" => 1 << 30,
+            _ => return Err(invalid()),
+        };
+        digits
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(multiplier))
+            .map(FileSize)
+            .ok_or_else(invalid)
+    }
+}
+
+/// A duration such as `30s`, `15m`, `2h`, `2d` or `1w`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Age(pub Duration);
+
+impl FromStr for Age {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Age, String> {
+        let invalid = || format!("invalid duration '{s}' (expected e.g. 30s, 15m, 2h, 2d or 1w)");
+        let (digits, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+        let seconds: u64 = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            "d" => 86_400,
+            "w" => 7 * 86_400,
+            _ => return Err(invalid()),
+        };
+        digits
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(seconds))
+            .map(|seconds| Age(Duration::from_secs(seconds)))
+            .ok_or_else(invalid)
+    }
+}
+
+/// A point in time, either a UTC date such as `2024-05-01` or `2024-05-01 12:30[:00]`, or a
+/// duration before now such as `2d`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointInTime {
+    Ago(Duration),
+    At(SystemTime),
+}
+
+impl PointInTime {
+    pub fn resolve(self, now: SystemTime) -> SystemTime {
+        match self {
+            PointInTime::Ago(age) => now.checked_sub(age).unwrap_or(SystemTime::UNIX_EPOCH),
+            PointInTime::At(time) => time,
+        }
+    }
+}
+
+impl FromStr for PointInTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PointInTime, String> {
+        if let Ok(Age(age)) = s.parse() {
+            return Ok(PointInTime::Ago(age));
+        }
+        parse_utc_datetime(s).map(PointInTime::At).ok_or_else(|| {
+            format!(
+                "invalid date '{s}' (expected YYYY-MM-DD, YYYY-MM-DD HH:MM[:SS] or a duration \
+                     such as 2d)"
+            )
+        })
+    }
+}
+
+/// Parse `YYYY-MM-DD`, optionally followed by ` HH:MM[:SS]` or `THH:MM[:SS]`, as a UTC time.
+fn parse_utc_datetime(s: &str) -> Option<SystemTime> {
+    let (date, time) = match s.split_once([' ', 'T']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (s, None),
+    };
+    let number = |part: &str, digits: usize| {
+        (part.len() == digits && part.bytes().all(|b| b.is_ascii_digit()))
+            .then(|| part.parse::<i64>().ok())
+            .flatten()
+    };
+    let mut date_parts = date.split('-');
+    let year = number(date_parts.next()?, 4)?;
+    let month = number(date_parts.next()?, 2)?;
+    let day = number(date_parts.next()?, 2)?;
+    if date_parts.next().is_some()
+        || !utils::days_in_month(year, month).is_some_and(|days| (1..=days).contains(&day))
+    {
+        return None;
+    }
+    let mut seconds = 0;
+    if let Some(time) = time {
+        let mut time_parts = time.split(':');
+        let hours = number(time_parts.next()?, 2)?;
+        let minutes = number(time_parts.next()?, 2)?;
+        let secs = time_parts.next().map_or(Some(0), |part| number(part, 2))?;
+        if time_parts.next().is_some() || hours > 23 || minutes > 59 || secs > 59 {
+            return None;
+        }
+        seconds = hours * 3600 + minutes * 60 + secs;
+    }
+    let days = utils::days_from_civil(year, month, day);
+    let timestamp = days * 86_400 + seconds;
+    let offset = Duration::from_secs(timestamp.unsigned_abs());
+    if timestamp >= 0 {
+        SystemTime::UNIX_EPOCH.checked_add(offset)
+    } else {
+        SystemTime::UNIX_EPOCH.checked_sub(offset)
+    }
+}
+
+/// Number of files walked before deciding whether a tree is small or large.
+const THREADS_SAMPLE_SIZE: usize = 256;
+/// Time the sample may take at most, the tree being deemed large past it: a slow walk comes from
+/// a large tree or from slow storage, which more threads help with either way.
+const THREADS_SAMPLE_TIME: Duration = Duration::from_millis(10);
+/// On small trees, spawning threads costs more than it saves past this many files per thread.
+const FILES_PER_THREAD: usize = 64;
+/// Upper bound used for trees that fit in the sample.
+const SMALL_TREE_MAX_THREADS: usize = 4;
+/// Upper bound used for large trees, past which walking contention outweighs the gains.
+const MAX_AUTO_THREADS: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Threads {
+    Auto,
+    Fixed(usize),
+}
+
+impl FromStr for Threads {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Threads, String> {
+        if s == "auto" {
+            return Ok(Threads::Auto);
+        }
+        match s.parse::<usize>() {
+            Ok(0) | Err(_) => Err(format!(
+                "invalid thread count '{s}' (expected 'auto' or a positive integer)"
+            )),
+            Ok(n) => Ok(Threads::Fixed(n)),
+        }
+    }
+}
+
+impl Threads {
+    /// Number of threads to search an explicit list of `n_files` files with.
+    pub fn for_files(self, n_files: usize) -> usize {
+        match self {
+            Threads::Auto => ThreadsEstimate::from_files(n_files).n_threads,
+            Threads::Fixed(n_threads) => n_threads,
+        }
+    }
+}
+
+impl fmt::Display for Threads {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Threads::Auto => write!(f, "auto"),
+            Threads::Fixed(n) => write!(f, "{n}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ThreadsEstimate {
+    pub n_threads: usize,
+    pub available_parallelism: usize,
+    pub sampled_files: usize,
+    /// whether the whole tree fit in the sample
+    pub sample_exhausted: bool,
+    /// time spent walking the sample, which the search walks again
+    pub sampling_time: Duration,
+}
+
+impl ThreadsEstimate {
+    /// Pick a thread count from the machine's available parallelism and an early estimate of
+    /// the tree size, obtained by sequentially walking its first few entries.
+    pub fn from_walk(builder: &WalkBuilder) -> ThreadsEstimate {
+        let started_at = Instant::now();
+        let mut walk = builder.build();
+        let mut sampled_files = 0;
+        let mut sample_exhausted = false;
+        while sampled_files < THREADS_SAMPLE_SIZE && started_at.elapsed() < THREADS_SAMPLE_TIME {
+            match walk.next() {
+                Some(Ok(entry)) if entry.file_type().is_some_and(|ft| !ft.is_dir()) => {
+                    sampled_files += 1;
+                }
+                Some(_) => {}
+                None => {
+                    sample_exhausted = true;
+                    break;
+                }
+            }
+        }
+        ThreadsEstimate {
+            sampling_time: started_at.elapsed(),
+            ..ThreadsEstimate::from_sample(sampled_files, sample_exhausted)
+        }
+    }
+
+    /// Same as for a walk, for an explicit list of files.
+    pub fn from_files(n_files: usize) -> ThreadsEstimate {
+        ThreadsEstimate::from_sample(
+            n_files.min(THREADS_SAMPLE_SIZE),
+            n_files < THREADS_SAMPLE_SIZE,
+        )
+    }
+
+    fn from_sample(sampled_files: usize, sample_exhausted: bool) -> ThreadsEstimate {
+        let available_parallelism = std::thread::available_parallelism().map_or(1, usize::from);
+        let n_threads = if sample_exhausted {
+            sampled_files
+                .div_ceil(FILES_PER_THREAD)
+                .clamp(1, available_parallelism.min(SMALL_TREE_MAX_THREADS))
+        } else {
+            available_parallelism.min(MAX_AUTO_THREADS)
+        };
+        ThreadsEstimate {
+            n_threads,
+            available_parallelism,
+            sampled_files,
+            sample_exhausted,
+            sampling_time: Duration::ZERO,
+        }
+    }
+}
+
+impl fmt::Display for ThreadsEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "available parallelism: {}", self.available_parallelism)?;
+        if self.sample_exhausted {
+            writeln!(f, "files to search: {}", self.sampled_files)?;
+        } else {
+            writeln!(f, "files to search: at least {}", self.sampled_files)?;
+        }
+        writeln!(
+            f,
+            "sampling cost: {:.3} ms (at most {THREADS_SAMPLE_SIZE} files or {} ms)",
+            self.sampling_time.as_secs_f64() * 1000.0,
+            THREADS_SAMPLE_TIME.as_millis()
+        )?;
+        writeln!(f, "threads: {}", self.n_threads)?;
+        if self.sample_exhausted {
+            write!(
+                f,
+                "reason: small tree, one thread per {FILES_PER_THREAD} files (at most {SMALL_TREE_MAX_THREADS})"
+            )
+        } else {
+            write!(
+                f,
+                "reason: large tree, one thread per logical cpu (at most {MAX_AUTO_THREADS})"
+            )
+        }
+    }
+}
+
+/// Build the filetype matcher used by the walker.
+///
+/// `added` definitions use ripgrep's `name:glob` syntax and are applied after `cleared` ones,
+/// so that a builtin type can be redefined from scratch.
+pub fn build_types(
+    selected: &[String],
+    negated: &[String],
+    added: &[String],
+    cleared: &[String],
+) -> Result<Types, GGError> {
+    let mut types_builder = filetypes_builder(added, cleared)?;
+    for ft in selected {
+        types_builder.select(ft);
+    }
+    for ft in negated {
+        types_builder.negate(ft);
+    }
+    types_builder.build().map_err(|err| match err {
+        Error::UnrecognizedFileType(name) => GGError::UnrecognizedFileType {
+            suggestions: similar_filetypes(&name, &types_builder.definitions()),
+            name,
+        },
+        err => GGError::Ignore(err),
+    })
+}
+
+/// All known filetype definitions, sorted by name.
+pub fn filetype_definitions(
+    added: &[String],
+    cleared: &[String],
+) -> Result<Vec<FileTypeDef>, GGError> {
+    Ok(filetypes_builder(added, cleared)?.definitions())
+}
+
+fn filetypes_builder(added: &[String], cleared: &[String]) -> Result<TypesBuilder, GGError> {
+    let mut types_builder = TypesBuilder::new();
+    types_builder.add_defaults();
+    add_custom_filetypes(&mut types_builder)?;
+    for ft in cleared {
+        types_builder.clear(ft);
+    }
+    for def in added {
+        types_builder.add_def(def)?;
+    }
+    Ok(types_builder)
 }
 
 fn add_custom_filetypes(types_builder: &mut TypesBuilder) -> Result<(), Error> {
     types_builder.add("pystrict", "*.py")
 }
 
+const MAX_FILETYPE_SUGGESTIONS: usize = 5;
+const FILETYPE_SIMILARITY_THRESHOLD: f64 = 0.8;
+
+fn similar_filetypes(name: &str, definitions: &[FileTypeDef]) -> Vec<String> {
+    let mut candidates: Vec<(f64, &str)> = definitions
+        .iter()
+        .map(|def| (strsim::jaro_winkler(name, def.name()), def.name()))
+        .filter(|(score, _)| *score >= FILETYPE_SIMILARITY_THRESHOLD)
+        .collect();
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+    candidates
+        .into_iter()
+        .take(MAX_FILETYPE_SUGGESTIONS)
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortKey {
+    Path,
+    Modified,
+    Accessed,
+    Created,
+}
+
+/// Sort results by the given key.
+///
+/// Files whose timestamps can't be read (e.g. `created` on some filesystems) are sorted first.
+pub fn sort_file_results(results: &mut [FileResults], key: SortKey, reverse: bool) {
+    match key {
+        SortKey::Path => results.sort_by(|a, b| a.path.cmp(&b.path)),
+        SortKey::Modified | SortKey::Accessed | SortKey::Created => {
+            results.sort_by_cached_key(|r| (file_timestamp(&r.path, key), r.path.clone()));
+        }
+    }
+    if reverse {
+        results.reverse();
+    }
+}
+
+fn file_timestamp(path: &Path, key: SortKey) -> Option<SystemTime> {
+    let metadata = path.metadata().ok()?;
+    match key {
+        SortKey::Path => None,
+        SortKey::Modified => metadata.modified().ok(),
+        SortKey::Accessed => metadata.accessed().ok(),
+        SortKey::Created => metadata.created().ok(),
+    }
+}
+
+/// Whether stdin is a pipe or a socket, whose data may keep trickling in as with `tail -f`.
+pub fn is_piped_stdin() -> bool {
+    #[cfg(unix)]
+    fn imp() -> bool {
+        use std::{
+            fs::File,
+            os::{fd::AsFd, unix::fs::FileTypeExt},
+        };
+
+        let Ok(fd) = std::io::stdin().as_fd().try_clone_to_owned() else {
+            return false;
+        };
+        File::from(fd)
+            .metadata()
+            .is_ok_and(|md| md.file_type().is_fifo() || md.file_type().is_socket())
+    }
+
+    #[cfg(not(unix))]
+    fn imp() -> bool {
+        true
+    }
+
+    imp()
+}
+
 // Original code from https://github.com/BurntSushi/ripgrep/blob/e0f1000df67f82ab0e735bad40e9b45b2d774ef0/crates/cli/src/lib.rs#L249
 pub fn is_readable_stdin() -> bool {
     use std::io::IsTerminal;
@@ -105,3 +626,61 @@ pub fn is_readable_stdin() -> bool {
 
     !std::io::stdin().is_terminal() && imp()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: u64) -> PointInTime {
+        PointInTime::At(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+    }
+
+    #[test]
+    fn dates_are_parsed_as_utc() {
+        assert_eq!("1970-01-01".parse(), Ok(at(0)));
+        assert_eq!("2024-02-29".parse(), Ok(at(1_709_164_800)));
+        assert_eq!("2024-02-29 23:30".parse(), Ok(at(1_709_249_400)));
+        assert_eq!("2024-02-29T23:30:15".parse(), Ok(at(1_709_249_415)));
+        assert_eq!(
+            "1969-12-31 23:59:59"
+                .parse::<PointInTime>()
+                .map(|time| time.resolve(SystemTime::now())),
+            Ok(SystemTime::UNIX_EPOCH - Duration::from_secs(1))
+        );
+        assert_eq!(
+            "2d".parse(),
+            Ok(PointInTime::Ago(Duration::from_secs(2 * 86_400)))
+        );
+    }
+
+    #[test]
+    fn invalid_dates_are_rejected() {
+        for date in [
+            "2023-02-29",
+            "2024-04-31",
+            "2024-13-01",
+            "2024-00-10",
+            "2024-1-10",
+            "2024-01-10 24:00",
+            "2024-01-10 12:60",
+            "2024-01-10 12:00:00:00",
+            "2024-01-10-01",
+            "yesterday",
+        ] {
+            assert!(date.parse::<PointInTime>().is_err(), "{date}");
+        }
+    }
+
+    #[test]
+    fn sizes_and_ages_have_units() {
+        assert_eq!("512".parse(), Ok(FileSize(512)));
+        assert_eq!("10K".parse(), Ok(FileSize(10 * 1024)));
+        assert_eq!("5mb".parse(), Ok(FileSize(5 * 1024 * 1024)));
+        assert!("5X".parse::<FileSize>().is_err());
+        assert!("K".parse::<FileSize>().is_err());
+        assert_eq!("90m".parse(), Ok(Age(Duration::from_secs(90 * 60))));
+        assert_eq!("1w".parse(), Ok(Age(Duration::from_secs(7 * 86_400))));
+        assert!("2".parse::<Age>().is_err());
+        assert!("2y".parse::<Age>().is_err());
+    }
+}
@@ -0,0 +1,431 @@
+use std::{
+    collections::HashSet,
+    path::{Component, Path, PathBuf},
+};
+
+use git2::{
+    Blob, Delta, DiffFile, DiffOptions, FileMode, ObjectType, Oid, Patch, Repository, Sort,
+    TreeWalkMode, TreeWalkResult,
+};
+use grep::searcher::Searcher;
+use serde::Serialize;
+
+use crate::{
+    search::{search_reader, PatternsMatcher, SearchResult},
+    utils, GGError,
+};
+
+/// What to search in the git repository holding the current directory, rather than the whole
+/// working tree.
+#[derive(Debug, Clone)]
+pub enum GitSearch {
+    /// the tree of a revision, e.g. a commit, a branch or a tag
+    Rev(String),
+    /// the index, i.e. what's staged for the next commit
+    Staged,
+    /// the files of the working tree changed since a revision, `HEAD` when none is given
+    Changed { since: Option<String> },
+}
+
+/// The revision files of the repository are searched in, e.g. for SARIF's version control
+/// provenance.
+#[derive(Debug, Clone)]
+pub struct Provenance {
+    /// URL of the `origin` remote, if any
+    pub remote_url: Option<String>,
+    /// the working tree, paths in the revision being relative to it
+    pub workdir: PathBuf,
+    /// the revision as given, e.g. `HEAD~1`
+    pub revision: String,
+    /// full id of the revision's commit, unless it names a tree
+    pub commit_id: Option<String>,
+}
+
+/// A file read from the repository's objects rather than from the working tree.
+pub struct GitFile {
+    /// path the file's results are reported under, e.g. `HEAD~1:src/main.rs`
+    pub label: PathBuf,
+    /// path of the file in the working tree
+    pub path: PathBuf,
+    pub id: Oid,
+}
+
+pub struct GitRepository {
+    repo: Repository,
+    workdir: PathBuf,
+}
+
+impl GitRepository {
+    /// The repository holding the current directory.
+    pub fn discover() -> Result<GitRepository, GGError> {
+        GitRepository::new(Repository::discover(std::env::current_dir()?)?)
+    }
+
+    /// Open the repository at `path` again, e.g. from another thread.
+    pub fn open(path: &Path) -> Result<GitRepository, GGError> {
+        GitRepository::new(Repository::open(path)?)
+    }
+
+    fn new(repo: Repository) -> Result<GitRepository, GGError> {
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| git2::Error::from_str("bare repositories aren't supported"))?
+            .canonicalize()?;
+        Ok(GitRepository { repo, workdir })
+    }
+
+    /// Path of the repository's `.git` directory.
+    pub fn path(&self) -> &Path {
+        self.repo.path()
+    }
+
+    pub fn blob(&self, id: Oid) -> Result<Blob<'_>, git2::Error> {
+        self.repo.find_blob(id)
+    }
+
+    /// Files of the tree of `rev` for which `keep` holds, given their path in the working tree.
+    pub fn rev_files(
+        &self,
+        rev: &str,
+        keep: impl Fn(&Path) -> bool,
+    ) -> Result<Vec<GitFile>, GGError> {
+        let tree = self.repo.revparse_single(rev)?.peel_to_tree()?;
+        let mut files = Vec::new();
+        tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() == Some(ObjectType::Blob) && is_regular_file(entry.filemode() as u32) {
+                if let Some(name) = entry.name() {
+                    let relative_path = format!("{dir}{name}");
+                    let path = self.workdir.join(&relative_path);
+                    if keep(&path) {
+                        files.push(GitFile {
+                            label: PathBuf::from(format!("{rev}:{relative_path}")),
+                            path,
+                            id: entry.id(),
+                        });
+                    }
+                }
+            }
+            TreeWalkResult::Ok
+        })?;
+        Ok(files)
+    }
+
+    /// Where the files of `rev` come from.
+    pub fn provenance(&self, rev: &str) -> Result<Provenance, GGError> {
+        let remote_url = self
+            .repo
+            .find_remote("origin")
+            .ok()
+            .and_then(|remote| remote.url().map(str::to_string));
+        let commit_id = self
+            .repo
+            .revparse_single(rev)?
+            .peel_to_commit()
+            .ok()
+            .map(|commit| commit.id().to_string());
+        Ok(Provenance {
+            remote_url,
+            workdir: self.workdir.clone(),
+            revision: rev.to_string(),
+            commit_id,
+        })
+    }
+
+    /// Files of the index for which `keep` holds, given their path in the working tree, the
+    /// conflicting versions of unmerged files aside.
+    pub fn staged_files(&self, keep: impl Fn(&Path) -> bool) -> Result<Vec<GitFile>, GGError> {
+        Ok(self
+            .repo
+            .index()?
+            .iter()
+            .filter(|entry| index_stage(entry.flags) == 0 && is_regular_file(entry.mode))
+            .filter_map(|entry| {
+                let relative_path = String::from_utf8_lossy(&entry.path);
+                let path = self.workdir.join(relative_path.as_ref());
+                keep(&path).then(|| GitFile {
+                    label: PathBuf::from(format!(":{relative_path}")),
+                    path,
+                    id: entry.id,
+                })
+            })
+            .collect())
+    }
+
+    /// Path of a file relative to the root of the working tree, for pathspecs.
+    pub fn relative_path(&self, path: &Path) -> Result<PathBuf, GGError> {
+        // normalized lexically, the path may not exist anymore
+        let mut normalized = PathBuf::new();
+        for component in std::env::current_dir()?.join(path).components() {
+            match component {
+                Component::ParentDir => {
+                    normalized.pop();
+                }
+                Component::CurDir => {}
+                component => normalized.push(component),
+            }
+        }
+        let path = normalized;
+        path.strip_prefix(&self.workdir)
+            .map(Path::to_path_buf)
+            .map_err(|_| {
+                GGError::Git(git2::Error::from_str(&format!(
+                    "{} is outside of the repository",
+                    path.display()
+                )))
+            })
+    }
+
+    /// Ids of the commits reachable from `HEAD`, the most recent first.
+    pub fn history(&self) -> Result<Vec<Oid>, GGError> {
+        let mut revwalk = self.repo.revwalk()?;
+        revwalk.set_sorting(Sort::TIME)?;
+        revwalk.push_head()?;
+        Ok(revwalk.collect::<Result<_, _>>()?)
+    }
+
+    /// The lines matching the patterns among the ones a commit adds or removes, in the files
+    /// under one of `pathspecs` if any, `None` when there are none. Merge commits aren't looked
+    /// at, as with `git log`.
+    pub fn commit_matches(
+        &self,
+        id: Oid,
+        pathspecs: &[PathBuf],
+        matcher: &PatternsMatcher,
+        searcher: &mut Searcher,
+    ) -> Result<Option<CommitMatches>, GGError> {
+        let commit = self.repo.find_commit(id)?;
+        if commit.parent_count() > 1 {
+            return Ok(None);
+        }
+        let parent_tree = match commit.parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+        let mut options = DiffOptions::new();
+        for pathspec in pathspecs {
+            options.pathspec(pathspec);
+        }
+        let diff = self.repo.diff_tree_to_tree(
+            parent_tree.as_ref(),
+            Some(&commit.tree()?),
+            Some(&mut options),
+        )?;
+        let mut files = Vec::new();
+        for (index, delta) in diff.deltas().enumerate() {
+            // diffing is what makes searching history slow, files are only diffed when the
+            // patterns match one of their versions
+            let matches_somewhere = [delta.old_file(), delta.new_file()]
+                .iter()
+                .map(|file| self.diff_blob(file))
+                .collect::<Result<Vec<_>, _>>()?
+                .iter()
+                .flatten()
+                .any(|blob| !blob.is_binary() && matcher.is_match(blob.content()));
+            if !matches_somewhere {
+                continue;
+            }
+            let Some(patch) = Patch::from_diff(&diff, index)? else {
+                continue;
+            };
+            let lines = changed_lines(&patch, matcher, searcher)?;
+            let path = delta.new_file().path().or(delta.old_file().path());
+            if let (false, Some(path)) = (lines.is_empty(), path) {
+                files.push(FileChanges {
+                    path: path.to_path_buf(),
+                    lines,
+                });
+            }
+        }
+        if files.is_empty() {
+            return Ok(None);
+        }
+        let author = commit.author();
+        Ok(Some(CommitMatches {
+            id: commit.id().to_string(),
+            author: format!(
+                "{} <{}>",
+                String::from_utf8_lossy(author.name_bytes()),
+                String::from_utf8_lossy(author.email_bytes())
+            ),
+            date: format_time(author.when()),
+            summary: commit.summary().unwrap_or_default().to_string(),
+            files,
+        }))
+    }
+
+    /// The blob of one side of a diff, if it is a regular file on that side.
+    fn diff_blob(&self, file: &DiffFile) -> Result<Option<Blob<'_>>, git2::Error> {
+        if file.id().is_zero() || !matches!(file.mode(), FileMode::Blob | FileMode::BlobExecutable)
+        {
+            return Ok(None);
+        }
+        self.repo.find_blob(file.id()).map(Some)
+    }
+
+    /// Files of the working tree that differ from `since`, `HEAD` by default, including the
+    /// untracked files that aren't ignored.
+    pub fn changed_files(&self, since: Option<&str>) -> Result<ChangedFiles, GGError> {
+        let tree = self
+            .repo
+            .revparse_single(since.unwrap_or("HEAD"))?
+            .peel_to_tree()?;
+        let mut options = DiffOptions::new();
+        options.include_untracked(true).recurse_untracked_dirs(true);
+        let diff = self
+            .repo
+            .diff_tree_to_workdir_with_index(Some(&tree), Some(&mut options))?;
+        let mut changed = ChangedFiles::default();
+        for delta in diff
+            .deltas()
+            .filter(|delta| delta.status() != Delta::Deleted)
+        {
+            if let Some(path) = delta.new_file().path() {
+                changed.insert(self.workdir.join(path));
+            }
+        }
+        Ok(changed)
+    }
+}
+
+/// A commit adding or removing lines matching the patterns, as reported by `gg log`.
+#[derive(Debug, Serialize)]
+pub struct CommitMatches {
+    pub id: String,
+    pub author: String,
+    pub date: String,
+    pub summary: String,
+    pub files: Vec<FileChanges>,
+}
+
+/// The matching lines a commit added to or removed from a file.
+#[derive(Debug, Serialize)]
+pub struct FileChanges {
+    pub path: PathBuf,
+    pub lines: Vec<ChangedLine>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangedLine {
+    /// `+` for added lines and `-` for removed ones, numbered in the new and old file respectively
+    pub origin: char,
+    #[serde(flatten)]
+    pub result: SearchResult,
+}
+
+/// Changed files of the working tree, along with the directories leading to them so that walks
+/// can skip the others.
+#[derive(Debug, Default)]
+pub struct ChangedFiles {
+    files: HashSet<PathBuf>,
+    dirs: HashSet<PathBuf>,
+}
+
+impl ChangedFiles {
+    fn insert(&mut self, path: PathBuf) {
+        for dir in path.ancestors().skip(1) {
+            if !self.dirs.insert(dir.to_path_buf()) {
+                break;
+            }
+        }
+        self.files.insert(path);
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.contains(path) || self.dirs.contains(path)
+    }
+}
+
+/// The added and removed lines of a patch matching the patterns.
+fn changed_lines(
+    patch: &Patch,
+    matcher: &PatternsMatcher,
+    searcher: &mut Searcher,
+) -> Result<Vec<ChangedLine>, GGError> {
+    // the lines are searched all at once, one per line of `text`
+    let mut text = String::new();
+    let mut origins = Vec::new();
+    for hunk in 0..patch.num_hunks() {
+        for index in 0..patch.num_lines_in_hunk(hunk)? {
+            let line = patch.line_in_hunk(hunk, index)?;
+            let line_number = match line.origin() {
+                '+' => line.new_lineno(),
+                '-' => line.old_lineno(),
+                _ => None,
+            };
+            if let Some(line_number) = line_number {
+                let content = String::from_utf8_lossy(line.content());
+                text.push_str(content.trim_end_matches(['\r', '\n']));
+                text.push('\n');
+                origins.push((line.origin(), u64::from(line_number)));
+            }
+        }
+    }
+    let mut lines = Vec::new();
+    search_reader(
+        text.as_bytes(),
+        None,
+        matcher,
+        searcher,
+        None,
+        |mut result| {
+            let index = usize::try_from(result.line_number - 1).map_err(std::io::Error::other)?;
+            let (origin, line_number) = origins[index];
+            result.line_number = line_number;
+            result.line_start = line_number;
+            result.line_end = line_number;
+            lines.push(ChangedLine { origin, result });
+            Ok(())
+        },
+    )?;
+    Ok(lines)
+}
+
+/// Format a git time as `YYYY-MM-DD HH:MM:SS +HHMM`, in the time zone it was recorded in.
+fn format_time(time: git2::Time) -> String {
+    let offset = i64::from(time.offset_minutes());
+    let local = time.seconds() + offset * 60;
+    let (days, seconds) = (local.div_euclid(86_400), local.rem_euclid(86_400));
+    let (year, month, day) = utils::civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} {}{:02}{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        time.sign(),
+        offset.abs() / 60,
+        offset.abs() % 60
+    )
+}
+
+/// Whether a git file mode is the one of a regular file, as opposed to symlinks and submodules.
+fn is_regular_file(mode: u32) -> bool {
+    mode & 0o170_000 == 0o100_000
+}
+
+/// Merge stage of an index entry, non-zero for the versions of a conflicting file.
+fn index_stage(flags: u16) -> u16 {
+    (flags >> 12) & 0b11
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_are_formatted_in_their_time_zone() {
+        // 2024-02-29 23:30:00 UTC
+        let seconds = 1_709_249_400;
+        assert_eq!(
+            format_time(git2::Time::new(seconds, 0)),
+            "2024-02-29 23:30:00 +0000"
+        );
+        assert_eq!(
+            format_time(git2::Time::new(seconds, 330)),
+            "2024-03-01 05:00:00 +0530"
+        );
+        assert_eq!(
+            format_time(git2::Time::new(seconds, -600)),
+            "2024-02-29 13:30:00 -1000"
+        );
+    }
+}
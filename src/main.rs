@@ -1,27 +1,45 @@
-use std::io::{self, stdin, Read};
-use std::path::PathBuf;
-use std::sync::{mpsc, Arc};
+use std::io::{self, stdin, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, OnceLock};
+use std::time::Instant;
 
 use clap::Parser;
+use git2::Oid;
 
-use cli::Commands;
-use fs::is_readable_stdin;
-use grep::regex::{self, RegexMatcher};
+use cli::{Commands, ConfigCommands};
+use fs::{is_piped_stdin, is_readable_stdin};
+use grep::regex;
 use ignore::DirEntry;
 use printer::PrinterConfig;
+use rules::Severity;
 use search::{build_searcher, search_reader};
+use stats::Stats;
 use thiserror::Error;
 use upgrade::upgrade_gg;
 
-use crate::cli::{process_cli_args, Cli};
-use crate::fs::walk_builder;
+use crate::cli::{process_cli_args, Cli, PostProcessedCli};
+use crate::fs::{
+    build_types, filetype_definitions, in_walk_scope, sort_file_results, walk_builder, Threads,
+    ThreadsEstimate,
+};
+use crate::git::{GitFile, GitRepository, GitSearch};
 use crate::printer::ResultsPrinter;
-use crate::search::{build_matcher, search_file, FileResults};
+use crate::search::{
+    build_matcher, search_contents, search_file, FileQuery, FileResults, PatternsMatcher,
+};
 
 mod cli;
+mod config;
 mod fs;
+mod git;
 mod printer;
+mod rules;
+mod sarif;
 mod search;
+mod stats;
 mod upgrade;
 mod utils;
 
@@ -31,103 +49,570 @@ pub enum GGError {
     Io(#[from] io::Error),
     #[error(transparent)]
     Regex(#[from] regex::Error),
+    #[error(transparent)]
+    Ignore(#[from] ignore::Error),
+    #[cfg(feature = "pcre2")]
+    #[error(transparent)]
+    Pcre2(#[from] grep::pcre2::Error),
+    #[cfg(not(feature = "pcre2"))]
+    #[error("PCRE2 is not available, gg needs to be built with the pcre2 feature for it")]
+    Pcre2Unavailable,
+    #[error("could not read pattern file {}: {source}", .path.display())]
+    PatternFile { path: PathBuf, source: io::Error },
+    #[error("no patterns to search for, the pattern files only hold empty lines and comments")]
+    EmptyPatternFiles,
+    #[error("could not read file list {}: {source}", .path.display())]
+    FileList { path: PathBuf, source: io::Error },
+    #[error("stdin can only be read once, by a single --pattern-file - or --files-from -")]
+    StdinReadTwice,
+    #[error("git: {}", .0.message())]
+    Git(#[from] git2::Error),
+    #[error("unrecognized file type: {name}{}", did_you_mean(.suggestions))]
+    UnrecognizedFileType {
+        name: String,
+        suggestions: Vec<String>,
+    },
+    #[error("invalid configuration file {}: {message}", .path.display())]
+    Config { path: PathBuf, message: String },
+    #[error("unknown profile: {0}")]
+    UnknownProfile(String),
+    #[error("invalid rules file {}: {message}", .path.display())]
+    Rules { path: PathBuf, message: String },
+}
+
+fn did_you_mean(suggestions: &[String]) -> String {
+    if suggestions.is_empty() {
+        String::new()
+    } else {
+        format!(" (did you mean: {}?)", suggestions.join(", "))
+    }
 }
 
-pub fn main() -> Result<(), GGError> {
-    let cli_args = process_cli_args(Cli::parse());
+/// Exit code when a rule with the `error` severity matched.
+const RULE_ERROR_EXIT_CODE: u8 = 1;
+const ERROR_EXIT_CODE: u8 = 1;
+/// Exit code of errors when searching for rules, which a matching rule already exits with 1 for.
+const ERROR_WITH_RULES_EXIT_CODE: u8 = 2;
+
+/// Number of commits `gg log` searches before printing the ones with matches.
+const LOG_BATCH_SIZE: usize = 256;
 
-    if let Some(subcommand) = cli_args.sub_command {
+/// Path stdin's results are reported under, unless labeled with `--label`.
+const STDIN_LABEL: &str = "stdin";
+
+pub fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let error_exit_code = if cli.rules.is_some() {
+        ERROR_WITH_RULES_EXIT_CODE
+    } else {
+        ERROR_EXIT_CODE
+    };
+    match run(cli) {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::from(error_exit_code)
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode, GGError> {
+    let started_at = Instant::now();
+    let mut cli_args = process_cli_args(cli)?;
+
+    if let Some(subcommand) = cli_args.sub_command.take() {
         match subcommand {
             Commands::Upgrade { force } => {
                 upgrade_gg(force);
-                return Ok(());
+                return Ok(ExitCode::SUCCESS);
+            }
+            Commands::Types => {
+                let mut stdout = io::stdout().lock();
+                let selected = |name: &str| {
+                    (cli_args.filter_filetypes.is_empty()
+                        || cli_args.filter_filetypes.iter().any(|t| t == name))
+                        && !cli_args.exclude_filetypes.iter().any(|t| t == name)
+                };
+                for def in filetype_definitions(&cli_args.add_filetypes, &cli_args.clear_filetypes)?
+                {
+                    if selected(def.name()) {
+                        writeln!(stdout, "{}: {}", def.name(), def.globs().join(", "))?;
+                    }
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+            Commands::Config {
+                command: ConfigCommands::Show,
+            } => {
+                let mut stdout = io::stdout().lock();
+                let settings = &cli_args.effective_settings;
+                // columns are as wide as their longest entry, plus a gap
+                let name_width = settings.iter().map(|s| s.name.len()).max().unwrap_or(0) + 2;
+                let value_width = settings
+                    .iter()
+                    .map(|s| s.value.chars().count())
+                    .max()
+                    .unwrap_or(0)
+                    + 2;
+                for setting in settings {
+                    writeln!(
+                        stdout,
+                        "{:<name_width$}{:<value_width$}{}",
+                        setting.name, setting.value, setting.source
+                    )?;
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+            Commands::Log {
+                paths, max_count, ..
+            } => return search_history(&cli_args, &paths, max_count),
+            Commands::Threads { .. } => {
+                let haystack_builder = walk_builder(
+                    cli_args.paths.iter().map(PathBuf::as_path).collect(),
+                    &cli_args.ignored_paths,
+                    &cli_args.ignore_rules,
+                    build_types(
+                        &cli_args.filter_filetypes,
+                        &cli_args.exclude_filetypes,
+                        &cli_args.add_filetypes,
+                        &cli_args.clear_filetypes,
+                    )?,
+                    None,
+                    cli_args.metadata_filters,
+                    None,
+                )?;
+                writeln!(
+                    io::stdout(),
+                    "{}",
+                    ThreadsEstimate::from_walk(&haystack_builder)
+                )?;
+                return Ok(ExitCode::SUCCESS);
             }
         }
     }
 
-    if is_readable_stdin() {
-        let stdin = stdin();
-        let mut buf = Vec::new();
-        if stdin.lock().read_to_end(&mut buf)? != 0 {
-            let matcher = build_matcher(&cli_args.patterns)?;
+    // stdin is searched unless it holds patterns or other files are to be searched, even when
+    // paths are given
+    if !cli_args.list_files
+        && !cli_args.stdin_consumed
+        && cli_args.files_from.is_none()
+        && cli_args.git.is_none()
+        && is_readable_stdin()
+    {
+        let mut stdin = stdin().lock();
+        // waits for the first of the input, which is streamed from then on
+        if !stdin.fill_buf()?.is_empty() {
+            let matcher = patterns_matcher(&cli_args)?;
             let mut searcher = build_searcher(cli_args.multiline);
-            match search_reader(buf.as_slice(), &matcher, &mut searcher) {
-                Ok(search_results) => {
-                    let printer_config = PrinterConfig {
-                        mode: cli_args.print_mode,
-                        absolute_paths: cli_args.absolute_paths,
-                        colored_output: cli_args.colored_output,
-                        disable_hyperlinks: cli_args.disable_hyperlinks,
-                        ..Default::default()
-                    };
-                    let mut printer = ResultsPrinter::new(printer_config);
-                    printer.write(FileResults {
-                        path: PathBuf::from("stdin"),
-                        results: search_results,
-                    })?;
-                    printer.wipeout()?;
-                }
-                Err(err) => {
-                    eprintln!("Error: {err}");
-                }
+            let mut printer_config = printer_config(&cli_args);
+            // piped data may trickle in, results are printed as they come
+            printer_config.line_buffered |= is_piped_stdin();
+            let mut printer = ResultsPrinter::new(printer_config);
+            let path = cli_args
+                .stdin_label
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(STDIN_LABEL));
+            let mut max_severity = None;
+            let label = cli_args.stdin_label.as_deref();
+            let stats = cli_args.stats.then(Stats::default);
+            let searched = search_reader(
+                stdin,
+                label,
+                &matcher,
+                &mut searcher,
+                stats.as_ref(),
+                |result| {
+                    if let Some(rules) = &cli_args.rules {
+                        max_severity =
+                            max_severity.max(rules.max_severity(slice::from_ref(&result)));
+                    }
+                    printer.write_streamed(&path, result)
+                },
+            );
+            if let Err(err) = searched {
+                eprintln!("Error: {err}");
+            }
+            printer.end_stream()?;
+            if let Some(stats) = stats {
+                printer.write_stats(&stats.summary(started_at))?;
             }
-            return Ok(());
+            printer.wipeout()?;
+            return Ok(rules_exit_code(max_severity));
         }
     }
 
-    let haystack_builder = walk_builder(
-        cli_args.paths.iter().map(PathBuf::as_path).collect(),
-        &cli_args.ignored_paths,
-        cli_args.n_threads,
-        !cli_args.disregard_gitignore,
+    let stats = cli_args.stats.then(|| Arc::new(Stats::default()));
+    let matcher: Option<Arc<PatternsMatcher>> = if cli_args.list_files {
+        None
+    } else {
+        Some(Arc::new(patterns_matcher(&cli_args)?))
+    };
+    let (tx, printer_queue) = mpsc::channel();
+
+    let types = build_types(
         &cli_args.filter_filetypes,
-    );
-    let matcher: Arc<RegexMatcher> = Arc::new(build_matcher(&cli_args.patterns)?);
+        &cli_args.exclude_filetypes,
+        &cli_args.add_filetypes,
+        &cli_args.clear_filetypes,
+    )?;
+    let multiline = cli_args.multiline;
+    let mut provenance = None;
 
-    let (tx, printer_queue) = mpsc::channel();
+    if let Some(files) = cli_args.files_from.take() {
+        let n_threads = cli_args.n_threads.for_files(files.len());
+        let stats = stats.clone();
+        std::thread::spawn(move || {
+            search_files(
+                &files,
+                n_threads,
+                matcher.as_deref(),
+                multiline,
+                stats.as_deref(),
+                &tx,
+            );
+        });
+    } else if let Some(git_search @ (GitSearch::Rev(_) | GitSearch::Staged)) = &cli_args.git {
+        let repo = GitRepository::discover()?;
+        let in_scope =
+            |path: &Path| in_walk_scope(path, &cli_args.paths, &cli_args.ignored_paths, &types);
+        let files = match git_search {
+            GitSearch::Rev(rev) => {
+                provenance = Some(repo.provenance(rev)?);
+                repo.rev_files(rev, in_scope)?
+            }
+            _ => repo.staged_files(in_scope)?,
+        };
+        let n_threads = cli_args.n_threads.for_files(files.len());
+        let repo_path = repo.path().to_path_buf();
+        let stats = stats.clone();
+        std::thread::spawn(move || {
+            search_git_files(
+                &repo_path,
+                &files,
+                n_threads,
+                matcher.as_deref(),
+                multiline,
+                stats.as_deref(),
+                &tx,
+            );
+        });
+    } else {
+        let changed_files = match &cli_args.git {
+            Some(GitSearch::Changed { since }) => Some(Arc::new(
+                GitRepository::discover()?.changed_files(since.as_deref())?,
+            )),
+            _ => None,
+        };
+        let haystack_builder = |stats: Option<Arc<Stats>>| {
+            walk_builder(
+                cli_args.paths.iter().map(PathBuf::as_path).collect(),
+                &cli_args.ignored_paths,
+                &cli_args.ignore_rules,
+                types.clone(),
+                changed_files.clone(),
+                cli_args.metadata_filters,
+                stats,
+            )
+        };
+        let n_threads = match cli_args.n_threads {
+            Threads::Fixed(n_threads) => n_threads,
+            // sampling the tree mustn't show up in the search's statistics
+            Threads::Auto => ThreadsEstimate::from_walk(&haystack_builder(None)?).n_threads,
+        };
+        let mut haystack_builder = haystack_builder(stats.clone())?;
+        haystack_builder.threads(n_threads);
 
-    std::thread::spawn(move || {
-        haystack_builder.build_parallel().run(|| {
-            let matcher = Arc::clone(&matcher);
-            let mut searcher = build_searcher(cli_args.multiline);
-            let tx = tx.clone();
-            Box::new(move |entry: Result<DirEntry, ignore::Error>| match entry {
-                Ok(entry) => {
-                    if !entry.path().is_dir() {
-                        let path = entry.path().to_path_buf();
-                        match search_file(path, &matcher, &mut searcher) {
-                            Ok(file_results) => {
-                                if !file_results.is_empty() {
-                                    tx.send(file_results).unwrap_or(());
-                                }
-                            }
-                            Err(_err) => (),
+        let walker_stats = stats.clone();
+        std::thread::spawn(move || {
+            haystack_builder.build_parallel().run(|| {
+                let matcher = matcher.clone();
+                let mut searcher = build_searcher(multiline);
+                let tx = tx.clone();
+                let stats = walker_stats.clone();
+                Box::new(move |entry: Result<DirEntry, ignore::Error>| match entry {
+                    Ok(entry) => {
+                        if !entry.path().is_dir() {
+                            send_results(
+                                entry.path().to_path_buf(),
+                                matcher.as_deref(),
+                                stats.as_deref(),
+                                &tx,
+                                |path, matcher| {
+                                    search_file(path, matcher, &mut searcher, stats.as_deref())
+                                },
+                            );
                         }
+                        ignore::WalkState::Continue
                     }
-                    ignore::WalkState::Continue
-                }
-                Err(err) => {
-                    eprintln!("Error: {err}");
-                    ignore::WalkState::Continue
-                }
-            })
+                    Err(err) => {
+                        eprintln!("Error: {err}");
+                        if let Some(stats) = &stats {
+                            stats.add_error();
+                        }
+                        ignore::WalkState::Continue
+                    }
+                })
+            });
         });
+    }
+
+    let mut printer = ResultsPrinter::new(PrinterConfig {
+        provenance,
+        ..printer_config(&cli_args)
     });
+    let mut max_severity = None;
+    let mut write = |result: FileResults| {
+        if let Some(rules) = &cli_args.rules {
+            max_severity = max_severity.max(rules.max_severity(&result.results));
+        }
+        printer.write(result)
+    };
+
+    if let Some(sort_key) = cli_args.sort {
+        // searching stays parallel, only the output order is serialized
+        let mut results: Vec<FileResults> = printer_queue.iter().collect();
+        sort_file_results(&mut results, sort_key, cli_args.sort_reverse);
+        for result in results {
+            write(result)?;
+        }
+    } else {
+        while let Ok(result) = printer_queue.recv() {
+            write(result)?;
+        }
+    }
+
+    if let Some(stats) = stats {
+        printer.write_stats(&stats.summary(started_at))?;
+    }
+    printer.wipeout()?;
+    Ok(rules_exit_code(max_severity))
+}
 
-    let printer_config = PrinterConfig {
+fn printer_config(cli_args: &PostProcessedCli) -> PrinterConfig {
+    PrinterConfig {
         mode: cli_args.print_mode,
         absolute_paths: cli_args.absolute_paths,
         colored_output: cli_args.colored_output,
-        disable_hyperlinks: cli_args.disable_hyperlinks,
+        color_specs: cli_args.color_specs.clone(),
+        // files read from git objects aren't the ones on disk
+        disable_hyperlinks: cli_args.disable_hyperlinks
+            || matches!(cli_args.git, Some(GitSearch::Rev(_) | GitSearch::Staged)),
+        hyperlink_format: cli_args.hyperlink_format.clone(),
         disable_devicons: !cli_args.enable_devicons,
-        ..Default::default()
+        heading: cli_args.heading,
+        line_numbers: cli_args.line_numbers,
+        path_terminator: cli_args.null_terminated_paths.then_some(b'\0'),
+        field_separator: cli_args.field_separator.clone(),
+        max_columns: cli_args.max_columns,
+        max_columns_preview: cli_args.max_columns_preview,
+        terminal_width: cli_args.terminal_width,
+        pager: cli_args.pager.clone(),
+        patterns: cli_args.patterns.clone(),
+        rules: cli_args.rules.clone(),
+        pattern_colors: cli_args.pattern_colors,
+        line_buffered: cli_args.line_buffered,
+        provenance: None,
+    }
+}
+
+/// Search the files of an explicit list, split among `n_threads` threads.
+fn search_files(
+    files: &[PathBuf],
+    n_threads: usize,
+    matcher: Option<&PatternsMatcher>,
+    multiline: bool,
+    stats: Option<&Stats>,
+    tx: &mpsc::Sender<FileResults>,
+) {
+    in_parallel(
+        files,
+        n_threads,
+        || build_searcher(multiline),
+        |searcher, path| {
+            send_results(path.clone(), matcher, stats, tx, |file, matcher| {
+                // unlike walked files, listed files are expected to be there and readable
+                search_file(file, matcher, searcher, stats)
+                    .inspect_err(|err| eprintln!("Error: {}: {err}", path.display()))
+            });
+        },
+    );
+}
+
+/// Search files read from the objects of the git repository at `repo_path`, split among
+/// `n_threads` threads each opening the repository, which can't be shared between them.
+fn search_git_files(
+    repo_path: &Path,
+    files: &[GitFile],
+    n_threads: usize,
+    matcher: Option<&PatternsMatcher>,
+    multiline: bool,
+    stats: Option<&Stats>,
+    tx: &mpsc::Sender<FileResults>,
+) {
+    in_parallel(
+        files,
+        n_threads,
+        || (build_searcher(multiline), GitRepository::open(repo_path)),
+        |(searcher, repo), file| {
+            send_results(file.label.clone(), matcher, stats, tx, |label, matcher| {
+                let repo = repo
+                    .as_ref()
+                    .map_err(|err| io::Error::other(err.to_string()))?;
+                let blob = repo.blob(file.id).map_err(io::Error::other)?;
+                search_contents(
+                    label,
+                    file.path.clone(),
+                    blob.content(),
+                    matcher,
+                    searcher,
+                    stats,
+                )
+            });
+        },
+    );
+}
+
+/// Search the lines added or removed by the commits of the repository holding the current
+/// directory, for `gg log`.
+fn search_history(
+    cli_args: &PostProcessedCli,
+    paths: &[PathBuf],
+    max_count: Option<usize>,
+) -> Result<ExitCode, GGError> {
+    let repo = GitRepository::discover()?;
+    let pathspecs = paths
+        .iter()
+        .map(|path| repo.relative_path(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let matcher = patterns_matcher(cli_args)?;
+    let commits = repo.history()?;
+    let n_threads = cli_args.n_threads.for_files(commits.len());
+    let repo_path = repo.path().to_path_buf();
+    let mut printer = ResultsPrinter::new(PrinterConfig {
+        // paths are the ones of past versions of files
+        disable_hyperlinks: true,
+        ..printer_config(cli_args)
+    });
+    let mut remaining = max_count.unwrap_or(usize::MAX);
+    // commits are searched in parallel a batch at a time, and reported in order
+    'batches: for batch in commits.chunks(LOG_BATCH_SIZE) {
+        if remaining == 0 {
+            break;
+        }
+        let searched: Vec<(Oid, OnceLock<_>)> =
+            batch.iter().map(|id| (*id, OnceLock::new())).collect();
+        in_parallel(
+            &searched,
+            n_threads,
+            || {
+                let repo = GitRepository::open(&repo_path).map_err(|err| err.to_string());
+                (build_searcher(false), repo)
+            },
+            |(searcher, repo), (id, matches)| {
+                let result = match repo {
+                    Ok(repo) => repo.commit_matches(*id, &pathspecs, &matcher, searcher),
+                    Err(err) => Err(GGError::Io(io::Error::other(err.clone()))),
+                };
+                matches.set(result).ok();
+            },
+        );
+        for (_, matches) in searched {
+            match matches.into_inner() {
+                Some(Ok(Some(commit))) => {
+                    printer.write_commit(&commit)?;
+                    remaining -= 1;
+                    if remaining == 0 {
+                        break 'batches;
+                    }
+                }
+                Some(Err(err)) => eprintln!("Error: {err}"),
+                _ => {}
+            }
+        }
+    }
+    printer.wipeout()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Call `f` on each of `items`, split among `n_threads` threads each having its own `state`.
+fn in_parallel<T: Sync, S>(
+    items: &[T],
+    n_threads: usize,
+    state: impl Fn() -> S + Sync,
+    f: impl Fn(&mut S, &T) + Sync,
+) {
+    let next_item = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..n_threads {
+            scope.spawn(|| {
+                let mut state = state();
+                while let Some(item) = items.get(next_item.fetch_add(1, Ordering::Relaxed)) {
+                    f(&mut state, item);
+                }
+            });
+        }
+    });
+}
+
+/// Send the results of `search` to the printer, the file only being listed under `path` when
+/// there is no matcher.
+fn send_results(
+    path: PathBuf,
+    matcher: Option<&PatternsMatcher>,
+    stats: Option<&Stats>,
+    tx: &mpsc::Sender<FileResults>,
+    search: impl FnOnce(PathBuf, &PatternsMatcher) -> io::Result<FileResults>,
+) {
+    if let Some(stats) = stats {
+        stats.add_walked();
+    }
+    let Some(matcher) = matcher else {
+        tx.send(FileResults {
+            path,
+            results: Vec::new(),
+        })
+        .unwrap_or(());
+        return;
     };
-    let mut printer = ResultsPrinter::new(printer_config);
+    match search(path, matcher) {
+        Ok(file_results) => {
+            if !file_results.is_empty() {
+                tx.send(file_results).unwrap_or(());
+            }
+        }
+        Err(_err) => {
+            if let Some(stats) = stats {
+                stats.add_error();
+            }
+        }
+    }
+}
 
-    while let Ok(result) = printer_queue.recv() {
-        printer.write(result)?;
+fn patterns_matcher(cli_args: &PostProcessedCli) -> Result<PatternsMatcher, GGError> {
+    // files matching the --none-of patterns are excluded, which needs telling them apart
+    let patterns = [cli_args.patterns.as_slice(), &cli_args.none_of].concat();
+    let build =
+        |patterns: &[String]| build_matcher(patterns, cli_args.engine, cli_args.multiline_dotall);
+    let mut matcher = build(&patterns).map_err(|err| match &cli_args.rules {
+        Some(rules) => rules.regex_error(err, |regex| build(&[regex.to_string()]).map(drop)),
+        None => err,
+    })?;
+    if let Some(rules) = &cli_args.rules {
+        matcher = matcher.with_rules(rules.clone());
     }
+    if cli_args.all_of || !cli_args.none_of.is_empty() {
+        matcher = matcher.with_query(FileQuery {
+            n_patterns: cli_args.patterns.len(),
+            all_of: cli_args.all_of,
+        });
+    }
+    Ok(matcher)
+}
 
-    printer.wipeout()?;
-    Ok(())
+fn rules_exit_code(max_severity: Option<Severity>) -> ExitCode {
+    if max_severity == Some(Severity::Error) {
+        ExitCode::from(RULE_ERROR_EXIT_CODE)
+    } else {
+        ExitCode::SUCCESS
+    }
 }
@@ -1,27 +1,47 @@
 use devicons::FileIcon;
+use serde::{Deserialize, Serialize};
 use std::{
-    env::current_dir,
+    borrow::Cow,
+    env::{self, current_dir},
     fmt,
-    io::{IsTerminal, Result, Write},
+    io::{ErrorKind, IsTerminal, Result, Write},
+    ops::Range,
     path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    str::FromStr,
+    sync::Arc,
 };
 use termcolor::{Buffer, BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
 
-use crate::search::{FileResults, SearchResult};
+use crate::git::{CommitMatches, Provenance};
+use crate::rules::{RuleSet, Severity};
+use crate::sarif::SarifLog;
+use crate::search::{FileResults, MatchRange, ResultLine, SearchResult};
+use crate::stats::StatsSummary;
+use crate::utils;
 use std::io::stdout;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, clap::ValueEnum)]
 pub enum PrintMode {
     Text,
     Json,
     Files,
+    Sarif,
 }
 
 pub struct ResultsPrinter {
-    writer: BufferWriter,
+    output: Output,
     buffer: Buffer,
     config: PrinterConfig,
     cwd: PathBuf,
+    /// colors of the matches of each pattern, when they differ from one pattern to the next
+    pattern_color_specs: Vec<ColorSpec>,
+    /// SARIF logs are a single document, written once all results are known
+    sarif_log: Option<SarifLog>,
+    /// whether results of a streamed file were written, its heading along with them
+    streaming: bool,
+    /// results of a streamed file in JSON, written as a single object once they're all known
+    streamed_results: Option<FileResults>,
 }
 
 pub struct PrinterConfig {
@@ -30,7 +50,33 @@ pub struct PrinterConfig {
     pub color_specs: ColorSpecs,
     pub absolute_paths: bool,
     pub disable_hyperlinks: bool,
+    pub hyperlink_format: HyperlinkFormat,
     pub disable_devicons: bool,
+    /// print the path once above its results rather than on every line
+    pub heading: bool,
+    pub line_numbers: bool,
+    /// byte written after paths instead of the usual newline or field separator
+    pub path_terminator: Option<u8>,
+    pub field_separator: String,
+    /// lines longer than this many characters are either omitted or previewed
+    pub max_columns: Option<usize>,
+    /// preview long lines around their matches instead of omitting them
+    pub max_columns_preview: bool,
+    /// width lines are fit into, along with their path and line number, when `max_columns` isn't
+    /// set
+    pub terminal_width: Option<usize>,
+    /// command paging the output when printing to a terminal
+    pub pager: Option<String>,
+    /// patterns searched for, which are the rules of SARIF output
+    pub patterns: Vec<String>,
+    /// rules the patterns come from, annotating the lines they match
+    pub rules: Option<Arc<RuleSet>>,
+    /// highlight the matches of each pattern in a different color
+    pub pattern_colors: bool,
+    /// print results as soon as they're written rather than once enough of them piled up
+    pub line_buffered: bool,
+    /// revision the files are read from, when searching git's objects
+    pub provenance: Option<Provenance>,
 }
 
 impl Default for PrinterConfig {
@@ -41,11 +87,26 @@ impl Default for PrinterConfig {
             color_specs: ColorSpecs::default(),
             absolute_paths: false,
             disable_hyperlinks: false,
+            hyperlink_format: HyperlinkFormat::default(),
             disable_devicons: false,
+            heading: true,
+            line_numbers: true,
+            path_terminator: None,
+            field_separator: String::from(":"),
+            max_columns: None,
+            max_columns_preview: false,
+            terminal_width: None,
+            pager: None,
+            patterns: Vec::new(),
+            rules: None,
+            pattern_colors: false,
+            line_buffered: false,
+            provenance: None,
         }
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct ColorSpecs {
     paths: ColorSpec,
     line_numbers: ColorSpec,
@@ -53,8 +114,35 @@ pub struct ColorSpecs {
     matched: ColorSpec,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// meant for dark terminal backgrounds
+    Default,
+    /// meant for light terminal backgrounds
+    Light,
+    /// keeps the terminal's own foreground color for lines
+    Terminal,
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Theme::Default => write!(f, "default"),
+            Theme::Light => write!(f, "light"),
+            Theme::Terminal => write!(f, "terminal"),
+        }
+    }
+}
+
 impl Default for ColorSpecs {
     fn default() -> ColorSpecs {
+        ColorSpecs::from_theme(Theme::Default)
+    }
+}
+
+impl ColorSpecs {
+    pub fn from_theme(theme: Theme) -> ColorSpecs {
         let mut paths: ColorSpec = ColorSpec::new();
         paths
             .set_fg(Some(Color::Green))
@@ -67,6 +155,17 @@ impl Default for ColorSpecs {
         lines.set_fg(Some(Color::White));
         let mut matched: ColorSpec = ColorSpec::new();
         matched.set_fg(Some(Color::Red)).set_bold(true);
+        match theme {
+            Theme::Default => {}
+            Theme::Light => {
+                paths.set_fg(Some(Color::Blue));
+                line_numbers.set_fg(Some(Color::Magenta));
+                lines.set_fg(None);
+            }
+            Theme::Terminal => {
+                lines.set_fg(None);
+            }
+        }
         ColorSpecs {
             paths,
             line_numbers,
@@ -74,6 +173,148 @@ impl Default for ColorSpecs {
             matched,
         }
     }
+
+    /// Variations of the match color, one per pattern, cycled through when there are more patterns.
+    fn pattern_palette(&self) -> Vec<ColorSpec> {
+        PATTERN_COLORS
+            .iter()
+            .map(|color| {
+                let mut spec = self.matched.clone();
+                spec.set_fg(Some(*color));
+                spec
+            })
+            .collect()
+    }
+
+    pub fn apply(&mut self, user_spec: &UserColorSpec) {
+        let spec = match user_spec.kind {
+            OutputKind::Path => &mut self.paths,
+            OutputKind::LineNumber => &mut self.line_numbers,
+            OutputKind::Line => &mut self.lines,
+            OutputKind::Match => &mut self.matched,
+        };
+        match user_spec.change {
+            ColorChange::None => spec.clear(),
+            ColorChange::Fg(color) => {
+                spec.set_fg(Some(color));
+            }
+            ColorChange::Bg(color) => {
+                spec.set_bg(Some(color));
+            }
+            ColorChange::Style(style, enabled) => {
+                match style {
+                    Style::Bold => spec.set_bold(enabled),
+                    Style::Italic => spec.set_italic(enabled),
+                    Style::Underline => spec.set_underline(enabled),
+                    Style::Intense => spec.set_intense(enabled),
+                    Style::Dimmed => spec.set_dimmed(enabled),
+                };
+            }
+        }
+    }
+}
+
+const PATTERN_COLORS: &[Color] = &[
+    Color::Red,
+    Color::Green,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::Yellow,
+];
+
+/// A user provided color specification, e.g. `path:fg:blue`, `match:style:nobold` or `line:none`.
+#[derive(Debug, Clone)]
+pub struct UserColorSpec {
+    spec: String,
+    kind: OutputKind,
+    change: ColorChange,
+}
+
+impl fmt::Display for UserColorSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.spec)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum OutputKind {
+    Path,
+    LineNumber,
+    Line,
+    Match,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ColorChange {
+    None,
+    Fg(Color),
+    Bg(Color),
+    Style(Style, bool),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Style {
+    Bold,
+    Italic,
+    Underline,
+    Intense,
+    Dimmed,
+}
+
+impl FromStr for UserColorSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<UserColorSpec, String> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let kind = match parts[0] {
+            "path" => OutputKind::Path,
+            "line-number" => OutputKind::LineNumber,
+            "line" => OutputKind::Line,
+            "match" => OutputKind::Match,
+            other => {
+                return Err(format!(
+                    "unknown output type '{other}' in color spec '{s}' (expected one of: path, line-number, line, match)"
+                ))
+            }
+        };
+        let change = match parts[1..] {
+            ["none"] => ColorChange::None,
+            ["fg", color] => ColorChange::Fg(parse_color(color)?),
+            ["bg", color] => ColorChange::Bg(parse_color(color)?),
+            ["style", style] => {
+                let (enabled, name) = match style.strip_prefix("no") {
+                    Some(name) => (false, name),
+                    None => (true, style),
+                };
+                let style = match name {
+                    "bold" => Style::Bold,
+                    "italic" => Style::Italic,
+                    "underline" => Style::Underline,
+                    "intense" => Style::Intense,
+                    "dimmed" => Style::Dimmed,
+                    _ => return Err(format!(
+                        "unknown style '{style}' in color spec '{s}' (expected one of: bold, italic, underline, intense, dimmed, or their 'no' prefixed variants)"
+                    )),
+                };
+                ColorChange::Style(style, enabled)
+            }
+            _ => {
+                return Err(format!(
+                    "invalid color spec '{s}' (expected {{type}}:{{fg|bg|style}}:{{value}} or {{type}}:none)"
+                ))
+            }
+        };
+        Ok(UserColorSpec {
+            spec: s.to_string(),
+            kind,
+            change,
+        })
+    }
+}
+
+fn parse_color(color: &str) -> std::result::Result<Color, String> {
+    color.parse::<Color>().map_err(|err| err.to_string())
 }
 
 impl ResultsPrinter {
@@ -83,34 +324,178 @@ impl ResultsPrinter {
             config.disable_hyperlinks = true;
             config.colored_output = false;
         }
-        let color_choice = if !config.colored_output || config.mode == PrintMode::Json {
+        let color_choice = if !config.colored_output
+            || matches!(config.mode, PrintMode::Json | PrintMode::Sarif)
+        {
             ColorChoice::Never
         } else {
             ColorChoice::Always
         };
-        let bufwriter = BufferWriter::stdout(color_choice);
-        let buffer = bufwriter.buffer();
+        let pager = config
+            .pager
+            .as_deref()
+            .filter(|_| stdout.is_terminal())
+            .and_then(spawn_pager);
+        let (output, buffer) = match pager {
+            Some(pager) => {
+                let buffer = if color_choice == ColorChoice::Never {
+                    Buffer::no_color()
+                } else {
+                    Buffer::ansi()
+                };
+                (Output::Pager(pager), buffer)
+            }
+            None => {
+                let bufwriter = BufferWriter::stdout(color_choice);
+                let buffer = bufwriter.buffer();
+                (Output::Stdout(bufwriter), buffer)
+            }
+        };
+        let sarif_log = (config.mode == PrintMode::Sarif).then(|| {
+            SarifLog::new(
+                &config.patterns,
+                config.rules.as_deref(),
+                config.provenance.clone(),
+            )
+        });
+        let pattern_color_specs = if config.pattern_colors && config.patterns.len() > 1 {
+            config.color_specs.pattern_palette()
+        } else {
+            Vec::new()
+        };
         ResultsPrinter {
-            writer: bufwriter,
+            output,
             buffer,
             config,
             cwd: current_dir().unwrap(),
+            pattern_color_specs,
+            sarif_log,
+            streaming: false,
+            streamed_results: None,
         }
     }
 
     const MAX_BUFFER_SIZE: usize = 1024;
 
     pub fn write(&mut self, results: FileResults) -> Result<()> {
-        if self.buffer.len() > Self::MAX_BUFFER_SIZE {
-            self.buffer.flush()?;
+        match self.config.mode {
+            PrintMode::Text => self.write_colored_text_results(&results.path, &results.results)?,
+            PrintMode::Json => {
+                let json = serde_json::to_string(&JsonFileResults::new(&results, &self.config))?;
+                self.writeln_to_buffer(&json)?;
+            }
+            PrintMode::Files => self.write_colored_path(&results.path)?,
+            PrintMode::Sarif => {
+                let uri = PathBuf::from(self.display_path(&results.path).into_owned());
+                if let Some(sarif_log) = &mut self.sarif_log {
+                    sarif_log.add(&results, &uri);
+                }
+            }
         }
+        self.flush_buffer()
+    }
+
+    /// Write a result of a file searched as a stream, e.g. stdin, before its other results are
+    /// known. In JSON, the file's results are still a single object, written by `wipeout`.
+    pub fn write_streamed(&mut self, path: &Path, result: SearchResult) -> Result<()> {
+        let first = !self.streaming;
+        self.streaming = true;
         match self.config.mode {
-            PrintMode::Text => self.write_colored_text_results(&results.path, &results.results),
-            PrintMode::Json => self.writeln_to_buffer(&serde_json::to_string(&FileResults {
-                path: results.path.clone(),
-                results: results.results,
-            })?),
-            PrintMode::Files => self.write_colored_path(&results.path),
+            PrintMode::Text => {
+                if first && self.config.heading {
+                    self.write_colored_path(path)?;
+                }
+                self.write_colored_search_results(path, std::slice::from_ref(&result))?;
+                self.flush_buffer()
+            }
+            PrintMode::Files if first => {
+                self.write_colored_path(path)?;
+                self.flush_buffer()
+            }
+            PrintMode::Files => Ok(()),
+            PrintMode::Json => {
+                self.streamed_results
+                    .get_or_insert_with(|| FileResults {
+                        path: path.to_path_buf(),
+                        results: Vec::new(),
+                    })
+                    .results
+                    .push(result);
+                Ok(())
+            }
+            PrintMode::Sarif => self.write(FileResults {
+                path: path.to_path_buf(),
+                results: vec![result],
+            }),
+        }
+    }
+
+    /// Print the buffer once it's full, or right away when line buffered.
+    fn flush_buffer(&mut self) -> Result<()> {
+        if self.config.line_buffered || self.buffer.len() > Self::MAX_BUFFER_SIZE {
+            self.output.print(&self.buffer)?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+
+    /// Write a commit found by `gg log`, with the matching lines it added or removed.
+    pub fn write_commit(&mut self, commit: &CommitMatches) -> Result<()> {
+        if self.config.mode == PrintMode::Json {
+            self.writeln_to_buffer(&serde_json::to_string(commit)?)?;
+            return self.flush_buffer();
+        }
+        self.buffer
+            .set_color(&self.config.color_specs.line_numbers)?;
+        write!(&mut self.buffer, "commit {}", commit.id)?;
+        self.buffer.reset()?;
+        writeln!(&mut self.buffer)?;
+        writeln!(&mut self.buffer, "Author: {}", commit.author)?;
+        writeln!(&mut self.buffer, "Date:   {}", commit.date)?;
+        writeln!(&mut self.buffer, "\n    {}\n", commit.summary)?;
+        for file in &commit.files {
+            self.write_colored_path(&file.path)?;
+            for changed_line in &file.lines {
+                for line in changed_line.result.lines() {
+                    self.buffer
+                        .set_color(&self.config.color_specs.line_numbers)?;
+                    write!(&mut self.buffer, "{}", changed_line.origin)?;
+                    let mut prefix_width = changed_line.origin.len_utf8();
+                    if self.config.line_numbers {
+                        self.write_line_number(&file.path, &line)?;
+                        prefix_width = self.line_number_end(prefix_width, line.line_number);
+                    }
+                    self.write_colored_matches(&line, prefix_width)?;
+                }
+            }
+            self.write_newline_to_buffer()?;
+        }
+        self.flush_buffer()
+    }
+
+    pub fn write_stats(&mut self, summary: &StatsSummary) -> Result<()> {
+        self.buffer.reset()?;
+        match self.config.mode {
+            PrintMode::Json => {
+                #[derive(Serialize)]
+                struct Summary<'a> {
+                    stats: &'a StatsSummary,
+                }
+                self.writeln_to_buffer(&serde_json::to_string(&Summary { stats: summary })?)
+            }
+            PrintMode::Sarif => {
+                if let Some(sarif_log) = &mut self.sarif_log {
+                    sarif_log.set_property("stats", serde_json::to_value(summary)?);
+                }
+                Ok(())
+            }
+            PrintMode::Text | PrintMode::Files => {
+                // text results with headings already end with an empty line
+                if !(self.config.mode == PrintMode::Text && self.config.heading) {
+                    self.write_newline_to_buffer()?;
+                }
+                write!(&mut self.buffer, "{summary}")
+            }
         }
     }
 
@@ -119,8 +504,11 @@ impl ResultsPrinter {
         path: &Path,
         search_results: &[SearchResult],
     ) -> Result<()> {
+        if !self.config.heading {
+            return self.write_colored_search_results(path, search_results);
+        }
         self.write_colored_path(path)?;
-        self.write_colored_search_results(search_results)?;
+        self.write_colored_search_results(path, search_results)?;
         self.write_newline_to_buffer()
     }
 
@@ -132,63 +520,215 @@ impl ResultsPrinter {
             )))?;
             write!(&mut self.buffer, "{} ", icon.icon)?;
         }
+        self.write_path(path)?;
+        match self.config.path_terminator {
+            Some(terminator) => self.buffer.write_all(&[terminator]),
+            None => writeln!(&mut self.buffer),
+        }
+    }
 
+    fn write_path(&mut self, path: &Path) -> Result<()> {
         self.buffer.set_color(&self.config.color_specs.paths)?;
-        let display_path = if self.config.absolute_paths {
+        let display_path = self.display_path(path).into_owned();
+        if self.config.disable_hyperlinks {
+            return write!(&mut self.buffer, "{display_path}");
+        }
+        let link = Hyperlink {
+            uri: &self.config.hyperlink_format.render(path, 1, 1),
+            id: None,
+        };
+        write!(&mut self.buffer, "{link}{display_path}{link:#}",)
+    }
+
+    fn display_path<'a>(&self, path: &'a Path) -> Cow<'a, str> {
+        if self.config.absolute_paths {
             path.to_string_lossy()
         } else if path.starts_with(&self.cwd) {
             path.strip_prefix(&self.cwd).unwrap().to_string_lossy()
         } else {
             path.to_string_lossy()
-        };
-        if self.config.disable_hyperlinks {
-            return writeln!(&mut self.buffer, "{display_path}");
         }
-        let path_str = path.to_string_lossy();
-        let link = Hyperlink {
-            uri: &format!("file://{path_str}"),
-            id: None,
-        };
-        writeln!(&mut self.buffer, "{link}{display_path}{link:#}",)
     }
 
-    fn write_colored_search_results(&mut self, results: &[SearchResult]) -> Result<()> {
+    fn write_path_separator(&mut self) -> Result<()> {
+        self.buffer.reset()?;
+        match self.config.path_terminator {
+            Some(terminator) => self.buffer.write_all(&[terminator]),
+            None => write!(&mut self.buffer, "{}", self.config.field_separator),
+        }
+    }
+
+    fn write_colored_search_results(
+        &mut self,
+        path: &Path,
+        results: &[SearchResult],
+    ) -> Result<()> {
         results.iter().try_for_each(|result| {
-            self.write_colored_line(result)?;
+            // multiline results are written one numbered line at a time
+            for line in result.lines() {
+                self.write_colored_line(path, &line)?;
+            }
+            if self.config.heading {
+                self.write_rule_annotations(result)?;
+            }
             Ok(())
         })
     }
 
-    fn write_colored_line(&mut self, result: &SearchResult) -> Result<()> {
+    fn write_colored_line(&mut self, path: &Path, line: &ResultLine) -> Result<()> {
+        // columns taken by what precedes the line
+        let mut prefix_width = 0;
+        if !self.config.heading {
+            self.write_path(path)?;
+            self.write_path_separator()?;
+            prefix_width += self.display_path(path).chars().count()
+                + self
+                    .config
+                    .path_terminator
+                    .map_or_else(|| self.config.field_separator.chars().count(), |_| 1);
+        }
+        if self.config.line_numbers {
+            self.write_line_number(path, line)?;
+            prefix_width = self.line_number_end(prefix_width, line.line_number);
+        }
+        self.write_colored_matches(line, prefix_width)
+    }
+
+    /// Column at which a line starts when its number is written at `column`, as done by
+    /// `write_line_number`.
+    fn line_number_end(&self, column: usize, line_number: u64) -> usize {
+        let end =
+            column + line_number.to_string().len() + self.config.field_separator.chars().count();
+        if self.config.heading {
+            // up to the next tab stop
+            (end / TAB_WIDTH + 1) * TAB_WIDTH
+        } else {
+            end
+        }
+    }
+
+    /// Write a `severity[id]: message` line below a line for each rule it matches.
+    fn write_rule_annotations(&mut self, result: &SearchResult) -> Result<()> {
+        let Some(rules) = self.config.rules.clone() else {
+            return Ok(());
+        };
+        let mut rule_indices: Vec<usize> = result.matches.iter().map(|m| m.pattern_index).collect();
+        rule_indices.sort_unstable();
+        rule_indices.dedup();
+        for rule in rule_indices
+            .into_iter()
+            .filter_map(|index| rules.get(index))
+        {
+            self.buffer.reset()?;
+            write!(&mut self.buffer, "\t")?;
+            self.buffer.set_color(&severity_color_spec(rule.severity))?;
+            write!(&mut self.buffer, "{}[{}]", rule.severity, rule.id)?;
+            self.buffer.reset()?;
+            match &rule.message {
+                Some(message) => writeln!(&mut self.buffer, ": {message}")?,
+                None => writeln!(&mut self.buffer)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn write_line_number(&mut self, path: &Path, result: &ResultLine) -> Result<()> {
         self.buffer
             .set_color(&self.config.color_specs.line_numbers)?;
-        write!(&mut self.buffer, "{}:\t", result.line_number)?;
-        self.write_colored_matches(result)
+        if self.config.disable_hyperlinks {
+            write!(&mut self.buffer, "{}", result.line_number)?;
+        } else {
+            // in unicode code points, like editors and SARIF count columns
+            let column = result
+                .matches
+                .first()
+                .map_or(1, |m| result.line[..m.start].chars().count() as u64 + 1);
+            let link = Hyperlink {
+                uri: &self
+                    .config
+                    .hyperlink_format
+                    .render(path, result.line_number, column),
+                id: None,
+            };
+            write!(&mut self.buffer, "{link}{}{link:#}", result.line_number)?;
+        }
+        write!(&mut self.buffer, "{}", self.config.field_separator)?;
+        if self.config.heading {
+            write!(&mut self.buffer, "\t")?;
+        }
+        Ok(())
     }
 
-    fn write_colored_matches(&mut self, result: &SearchResult) -> Result<()> {
+    /// Write a line preceded by `prefix_width` columns of path and line number, fitting it to the
+    /// terminal's width when not given a maximum number of columns.
+    fn write_colored_matches(&mut self, result: &ResultLine, prefix_width: usize) -> Result<()> {
         self.buffer.set_color(&self.config.color_specs.lines)?;
-        let mut last_end_offset = 0;
-        result
-            .matches
+        let max_columns = self.config.max_columns.or_else(|| {
+            self.config
+                .terminal_width
+                .map(|width| width.saturating_sub(prefix_width))
+        });
+        if let Some(max_columns) = max_columns {
+            let content = result.line.trim_end_matches(['\r', '\n']);
+            if content.chars().count() > max_columns {
+                if !self.config.max_columns_preview {
+                    return writeln!(
+                        &mut self.buffer,
+                        "[omitted long line with {} matches]",
+                        result.matches.len()
+                    );
+                }
+                // leaving room for the ellipses around the preview
+                let windows =
+                    preview_windows(content, &result.matches, max_columns.saturating_sub(2));
+                if windows.first().is_some_and(|w| w.start > 0) {
+                    write!(&mut self.buffer, "{ELLIPSIS}")?;
+                }
+                for (i, window) in windows.iter().enumerate() {
+                    if i > 0 {
+                        write!(&mut self.buffer, "{ELLIPSIS}")?;
+                    }
+                    self.write_colored_range(result.line, window.clone(), &result.matches)?;
+                }
+                if windows.last().is_some_and(|w| w.end < content.len()) {
+                    write!(&mut self.buffer, "{ELLIPSIS}")?;
+                }
+                return writeln!(&mut self.buffer);
+            }
+        }
+        self.write_colored_range(result.line, 0..result.line.len(), &result.matches)
+    }
+
+    /// Write `line[range]`, highlighting the parts of it that are matches.
+    fn write_colored_range(
+        &mut self,
+        line: &str,
+        range: Range<usize>,
+        matches: &[MatchRange],
+    ) -> Result<()> {
+        let mut last_end_offset = range.start;
+        matches
             .iter()
+            .filter(|m| m.start >= range.start && m.end <= range.end)
             .try_for_each(|match_range| -> Result<()> {
-                write!(
-                    &mut self.buffer,
-                    "{}",
-                    &result.line[last_end_offset..match_range.start]
-                )?;
-                self.buffer.set_color(&self.config.color_specs.matched)?;
-                write!(
-                    &mut self.buffer,
-                    "{}",
-                    &result.line[match_range.start..match_range.end]
-                )?;
+                // the matches of several rules can overlap, in which case the overlap is written
+                // once
+                let start = match_range.start.max(last_end_offset);
+                if start > match_range.start && start >= match_range.end {
+                    return Ok(());
+                }
+                write!(&mut self.buffer, "{}", &line[last_end_offset..start])?;
+                let matched = self
+                    .pattern_color_specs
+                    .get(match_range.pattern_index % self.pattern_color_specs.len().max(1))
+                    .unwrap_or(&self.config.color_specs.matched);
+                self.buffer.set_color(matched)?;
+                write!(&mut self.buffer, "{}", &line[start..match_range.end])?;
                 self.buffer.set_color(&self.config.color_specs.lines)?;
                 last_end_offset = match_range.end;
                 Ok(())
             })?;
-        write!(&mut self.buffer, "{}", &result.line[last_end_offset..])
+        write!(&mut self.buffer, "{}", &line[last_end_offset..range.end])
     }
 
     fn writeln_to_buffer(&mut self, text: &str) -> Result<()> {
@@ -201,18 +741,221 @@ impl ResultsPrinter {
         writeln!(self.buffer, "{}", Self::EMPTY_STRING)
     }
 
+    /// Finish writing a file searched as a stream: its JSON object, or the empty line ending its
+    /// results under a heading.
+    pub fn end_stream(&mut self) -> Result<()> {
+        if self.streaming && self.config.mode == PrintMode::Text && self.config.heading {
+            self.write_newline_to_buffer()?;
+        }
+        self.streaming = false;
+        match self.streamed_results.take() {
+            Some(results) => self.write(results),
+            None => Ok(()),
+        }
+    }
+
     pub fn wipeout(&mut self) -> Result<()> {
+        self.end_stream()?;
+        if let Some(sarif_log) = self.sarif_log.take() {
+            self.writeln_to_buffer(&serde_json::to_string_pretty(&sarif_log.to_json())?)?;
+        }
         self.buffer.flush()?;
-        self.reset_ansi_formatting()
+        self.reset_ansi_formatting()?;
+        self.output.close()
     }
 
     fn reset_ansi_formatting(&mut self) -> Result<()> {
         self.buffer.reset()?;
         write!(&mut self.buffer, "")?;
-        self.writer.print(&self.buffer)
+        self.output.print(&self.buffer)
+    }
+}
+
+/// Results as printed in JSON, each match telling which pattern produced it.
+#[derive(Serialize)]
+struct JsonFileResults<'a> {
+    path: &'a Path,
+    results: Vec<JsonSearchResult<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonSearchResult<'a> {
+    line_number: u64,
+    line: &'a str,
+    line_start: u64,
+    line_end: u64,
+    matches: Vec<JsonMatch<'a>>,
+    /// the result's lines with their own match offsets, for results spanning several lines
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    lines: Vec<JsonLine<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    line_number: u64,
+    line: &'a str,
+    matches: Vec<JsonMatch<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonMatch<'a> {
+    #[serde(flatten)]
+    range: MatchRange,
+    pattern: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rule: Option<&'a str>,
+}
+
+impl<'a> JsonFileResults<'a> {
+    fn new(file_results: &'a FileResults, config: &'a PrinterConfig) -> JsonFileResults<'a> {
+        let json_match = |m: &MatchRange| JsonMatch {
+            range: m.clone(),
+            pattern: config.patterns.get(m.pattern_index).map(String::as_str),
+            rule: config
+                .rules
+                .as_ref()
+                .and_then(|rules| rules.get(m.pattern_index))
+                .map(|rule| rule.id.as_str()),
+        };
+        let results = file_results
+            .results
+            .iter()
+            .map(|result| JsonSearchResult {
+                line_number: result.line_number,
+                line: &result.line,
+                line_start: result.line_start,
+                line_end: result.line_end,
+                matches: result.matches.iter().map(json_match).collect(),
+                lines: if result.line_end > result.line_start {
+                    result
+                        .lines()
+                        .map(|line| JsonLine {
+                            line_number: line.line_number,
+                            line: line.line,
+                            matches: line.matches.iter().map(json_match).collect(),
+                        })
+                        .collect()
+                } else {
+                    Vec::new()
+                },
+            })
+            .collect();
+        JsonFileResults {
+            path: &file_results.path,
+            results,
+        }
     }
 }
 
+/// Where the printer's output ends up: stdout, or the stdin of a pager process.
+enum Output {
+    Stdout(BufferWriter),
+    Pager(Child),
+}
+
+impl Output {
+    fn print(&mut self, buffer: &Buffer) -> Result<()> {
+        match self {
+            Output::Stdout(writer) => writer.print(buffer),
+            Output::Pager(pager) => match pager.stdin.as_mut() {
+                Some(stdin) => match stdin.write_all(buffer.as_slice()) {
+                    // the pager was quit before reading all of the output
+                    Err(err) if err.kind() == ErrorKind::BrokenPipe => Ok(()),
+                    result => result,
+                },
+                None => Ok(()),
+            },
+        }
+    }
+
+    fn close(&mut self) -> Result<()> {
+        if let Output::Pager(pager) = self {
+            drop(pager.stdin.take());
+            pager.wait()?;
+        }
+        Ok(())
+    }
+}
+
+/// Options `less` needs to page results: interpret colors and hyperlinks, quit right away if the
+/// output fits on one screen and keep it on screen afterwards.
+const REQUIRED_LESS_OPTIONS: &str = "FRX";
+
+fn spawn_pager(command: &str) -> Option<Child> {
+    let args = match shell_words::split(command) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("warning: ignoring pager '{command}': {err}");
+            return None;
+        }
+    };
+    let (program, args) = args.split_first()?;
+    let mut pager = Command::new(program);
+    pager.args(args).stdin(Stdio::piped());
+    pager.env("LESS", less_options(env::var("LESS").ok().as_deref()));
+    pager
+        .spawn()
+        .inspect_err(|err| eprintln!("warning: could not start pager '{command}': {err}"))
+        .ok()
+}
+
+/// The user's `LESS` options followed by the ones gg relies on.
+fn less_options(user_options: Option<&str>) -> String {
+    match user_options
+        .map(str::trim)
+        .filter(|options| !options.is_empty())
+    {
+        Some(options) => format!("{options} -{REQUIRED_LESS_OPTIONS}"),
+        None => REQUIRED_LESS_OPTIONS.to_string(),
+    }
+}
+
+const ELLIPSIS: &str = "…";
+
+/// Columns between tab stops in terminals.
+const TAB_WIDTH: usize = 8;
+
+/// Byte ranges of `line` that fit in about `max_columns` characters, each of them centered on one
+/// or more matches. Ranges always fall on character boundaries.
+fn preview_windows(line: &str, matches: &[MatchRange], max_columns: usize) -> Vec<Range<usize>> {
+    let line_start = [MatchRange::zero(0)];
+    let matches = if matches.is_empty() {
+        &line_start
+    } else {
+        matches
+    };
+    let budget = max_columns / matches.len();
+    let mut windows: Vec<Range<usize>> = Vec::new();
+    for m in matches {
+        let context = budget.saturating_sub(line[m.start..m.end].chars().count()) / 2;
+        let start = line[..m.start]
+            .char_indices()
+            .rev()
+            .take(context)
+            .last()
+            .map_or(m.start, |(i, _)| i);
+        let end = line[m.end..]
+            .char_indices()
+            .nth(context)
+            .map_or(line.len(), |(i, _)| m.end + i);
+        match windows.last_mut() {
+            Some(last) if start <= last.end => last.end = last.end.max(end),
+            _ => windows.push(start..end),
+        }
+    }
+    windows
+}
+
+fn severity_color_spec(severity: Severity) -> ColorSpec {
+    let mut spec = ColorSpec::new();
+    spec.set_bold(true).set_fg(Some(match severity {
+        Severity::Error => Color::Red,
+        Severity::Warning => Color::Yellow,
+        Severity::Note => Color::Cyan,
+    }));
+    spec
+}
+
 fn devicons_to_termcolor_color(d_color: &str) -> Option<Color> {
     d_color.strip_prefix("#").and_then(|hex| {
         if hex.len() != 6 {
@@ -225,10 +968,113 @@ fn devicons_to_termcolor_color(d_color: &str) -> Option<Color> {
     })
 }
 
+/// How hyperlinks are rendered, either one of the presets or a template using the `{path}`,
+/// `{line}`, `{column}` and `{host}` placeholders, e.g. `vscode://file{path}:{line}:{column}`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct HyperlinkFormat {
+    name: String,
+    template: String,
+    host: Option<String>,
+}
+
+const HYPERLINK_PRESETS: &[(&str, &str)] = &[
+    ("default", "file://{path}"),
+    ("file", "file://{host}{path}"),
+    ("vscode", "vscode://file{path}:{line}:{column}"),
+    ("cursor", "cursor://file{path}:{line}:{column}"),
+    ("kitty", "file://{host}{path}#{line}"),
+    ("wezterm", "file://{host}{path}#{line}"),
+    (
+        "idea",
+        "idea://open?file={path}&line={line}&column={column}",
+    ),
+];
+
+const HYPERLINK_PLACEHOLDERS: &[&str] = &["path", "line", "column", "host"];
+
+impl HyperlinkFormat {
+    /// Render the hyperlink to a position in a file, `line` and `column` being one-based.
+    pub fn render(&self, path: &Path, line: u64, column: u64) -> String {
+        self.template
+            .replace(
+                "{path}",
+                &utils::percent_encode(path.as_os_str().as_encoded_bytes()),
+            )
+            .replace("{line}", &line.to_string())
+            .replace("{column}", &column.to_string())
+            .replace("{host}", self.host.as_deref().unwrap_or_default())
+    }
+}
+
+impl Default for HyperlinkFormat {
+    fn default() -> HyperlinkFormat {
+        "default".parse().unwrap()
+    }
+}
+
+impl FromStr for HyperlinkFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<HyperlinkFormat, String> {
+        let template = HYPERLINK_PRESETS
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map_or(s, |(_, template)| template);
+        if !template.contains("{path}") {
+            let presets: Vec<&str> = HYPERLINK_PRESETS.iter().map(|(name, _)| *name).collect();
+            return Err(format!(
+                "invalid hyperlink format '{s}': expected one of {} or a template containing {{path}}",
+                presets.join(", ")
+            ));
+        }
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let Some(end) = rest[start..].find('}') else {
+                return Err(format!(
+                    "invalid hyperlink format '{s}': unclosed placeholder"
+                ));
+            };
+            let placeholder = &rest[start + 1..start + end];
+            if !HYPERLINK_PLACEHOLDERS.contains(&placeholder) {
+                return Err(format!(
+                    "invalid hyperlink format '{s}': unknown placeholder {{{placeholder}}} (expected one of {{{}}})",
+                    HYPERLINK_PLACEHOLDERS.join("}, {")
+                ));
+            }
+            rest = &rest[start + end..];
+        }
+        Ok(HyperlinkFormat {
+            name: s.to_string(),
+            template: template.to_string(),
+            host: template
+                .contains("{host}")
+                .then(|| gethostname::gethostname().to_string_lossy().into_owned()),
+        })
+    }
+}
+
+impl TryFrom<String> for HyperlinkFormat {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<HyperlinkFormat, String> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for HyperlinkFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
 #[derive(Default, Debug, PartialEq, Clone)]
 pub struct Hyperlink<'a> {
     // maybe this should use u8 to support non-utf encodings?
     uri: &'a str,
+    /// Terminals only treat cells with the same id as a single link when their URIs are the same
+    /// too, so ids can't group a heading with the links of its lines, whose URIs differ by their
+    /// line and column, and are left unset.
     id: Option<&'a str>,
 }
 
@@ -251,3 +1097,36 @@ impl fmt::Display for Hyperlink<'_> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn less_options_keep_the_user_ones() {
+        assert_eq!(less_options(None), "FRX");
+        assert_eq!(less_options(Some(" ")), "FRX");
+        assert_eq!(less_options(Some("-i -j5")), "-i -j5 -FRX");
+    }
+
+    #[test]
+    fn hyperlink_paths_are_percent_encoded() {
+        let path = Path::new("/tmp/dir #1/a b?%é.txt");
+        let encoded = "/tmp/dir%20%231/a%20b%3F%25%C3%A9.txt";
+        let kitty: HyperlinkFormat = "kitty".parse().unwrap();
+        assert_eq!(
+            kitty.render(path, 3, 1),
+            format!("file://{}{encoded}#3", kitty.host.as_deref().unwrap())
+        );
+        let vscode: HyperlinkFormat = "vscode".parse().unwrap();
+        assert_eq!(
+            vscode.render(path, 3, 7),
+            format!("vscode://file{encoded}:3:7")
+        );
+        let custom: HyperlinkFormat = "editor://{path}?l={line}".parse().unwrap();
+        assert_eq!(
+            custom.render(Path::new("/a-b_c.d~e/f"), 3, 7),
+            "editor:///a-b_c.d~e/f?l=3"
+        );
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use grep::{
+    matcher::Matcher,
+    regex::{RegexMatcher, RegexMatcherBuilder},
+};
+use ignore::overrides::{Override, OverrideBuilder};
+use serde::Deserialize;
+
+use crate::{search::SearchResult, GGError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Note,
+    #[default]
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Note => write!(f, "note"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A rule as written in a rules file, e.g.
+///
+/// ```toml
+/// [[rule]]
+/// id = "no-dbg"
+/// regex = 'dbg!\('
+/// message = "remove debugging macros before committing"
+/// severity = "error"
+/// globs = ["*.rs", "!tests/"]
+/// allowlist = ['// gg: allow']
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleDefinition {
+    id: String,
+    regex: String,
+    message: Option<String>,
+    #[serde(default)]
+    severity: Severity,
+    /// gitignore style globs of the files the rule applies to, relative to the rules file
+    #[serde(default)]
+    globs: Vec<String>,
+    /// matches on lines also matching one of these regexes are ignored
+    #[serde(default)]
+    allowlist: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    rule: Vec<RuleDefinition>,
+}
+
+#[derive(Debug)]
+pub struct Rule {
+    pub id: String,
+    pub regex: String,
+    pub message: Option<String>,
+    pub severity: Severity,
+    scope: Option<Override>,
+    allowlist: Option<RegexMatcher>,
+}
+
+impl Rule {
+    fn applies_to(&self, path: Option<&Path>) -> bool {
+        match (&self.scope, path) {
+            // like when walking, a file in an excluded directory is excluded as well. Files no
+            // glob matches are only ignored when some of the globs aren't exclusions.
+            (Some(scope), Some(path)) => {
+                !scope.matched(path, false).is_ignore()
+                    && !path
+                        .ancestors()
+                        .skip(1)
+                        .take_while(|dir| dir.starts_with(scope.path()) && *dir != scope.path())
+                        .any(|dir| scope.matched(dir, true).is_ignore())
+            }
+            _ => true,
+        }
+    }
+
+    fn allows(&self, line: &str) -> bool {
+        self.allowlist
+            .as_ref()
+            .is_some_and(|allowlist| allowlist.is_match(line.as_bytes()).unwrap_or(false))
+    }
+}
+
+/// Named patterns loaded from a rules file, the index of a rule being the index of its pattern.
+#[derive(Debug)]
+pub struct RuleSet {
+    /// the rules file
+    path: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    pub fn load(path: &Path) -> Result<RuleSet, GGError> {
+        let source = std::fs::read_to_string(path)?;
+        // globs are relative to the directory holding the rules file
+        let root = path
+            .canonicalize()?
+            .parent()
+            .map_or_else(PathBuf::new, Path::to_path_buf);
+        RuleSet::parse(path, &source, &root)
+    }
+
+    /// Parse the rules of the rules file at `path`, whose globs are relative to `root`.
+    fn parse(path: &Path, source: &str, root: &Path) -> Result<RuleSet, GGError> {
+        let invalid_rules = |message: String| GGError::Rules {
+            path: path.to_path_buf(),
+            message,
+        };
+        let file: RulesFile =
+            toml::from_str(source).map_err(|err| invalid_rules(err.message().to_string()))?;
+        if file.rule.is_empty() {
+            return Err(invalid_rules(String::from("no rules defined")));
+        }
+        let mut ids = HashSet::new();
+        let rules = file
+            .rule
+            .into_iter()
+            .map(|definition| {
+                if !ids.insert(definition.id.clone()) {
+                    return Err(invalid_rules(format!(
+                        "duplicate rule id '{}'",
+                        definition.id
+                    )));
+                }
+                let in_rule = |err: &dyn fmt::Display| {
+                    invalid_rules(format!("rule '{}': {err}", definition.id))
+                };
+                let scope = if definition.globs.is_empty() {
+                    None
+                } else {
+                    let mut builder = OverrideBuilder::new(root);
+                    for glob in &definition.globs {
+                        builder.add(glob).map_err(|err| in_rule(&err))?;
+                    }
+                    Some(builder.build().map_err(|err| in_rule(&err))?)
+                };
+                let allowlist = if definition.allowlist.is_empty() {
+                    None
+                } else {
+                    Some(
+                        RegexMatcherBuilder::new()
+                            .build_many(&definition.allowlist)
+                            .map_err(|err| in_rule(&err))?,
+                    )
+                };
+                Ok(Rule {
+                    id: definition.id,
+                    regex: definition.regex,
+                    message: definition.message,
+                    severity: definition.severity,
+                    scope,
+                    allowlist,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(RuleSet {
+            path: path.to_path_buf(),
+            rules,
+        })
+    }
+
+    /// Name the rule at fault for `err`, an error compiling the rules' regexes, i.e. the first one
+    /// `compile` rejects on its own. Regexes are only compiled along with the other patterns, once
+    /// the engine searching them is known.
+    pub fn regex_error(
+        &self,
+        err: GGError,
+        compile: impl Fn(&str) -> Result<(), GGError>,
+    ) -> GGError {
+        self.rules
+            .iter()
+            .find_map(|rule| {
+                compile(&rule.regex).err().map(|err| GGError::Rules {
+                    path: self.path.clone(),
+                    message: format!("rule '{}': {err}", rule.id),
+                })
+            })
+            .unwrap_or(err)
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Rule> {
+        self.rules.get(index)
+    }
+
+    pub fn patterns(&self) -> Vec<String> {
+        self.rules.iter().map(|rule| rule.regex.clone()).collect()
+    }
+
+    /// Drop the matches of rules that don't apply to `path` or that are allowlisted on their line,
+    /// `path` being `None` when searching stdin.
+    pub fn retain_applicable(&self, path: Option<&Path>, results: &mut Vec<SearchResult>) {
+        let applies: Vec<bool> = self
+            .rules
+            .iter()
+            .map(|rule| rule.applies_to(path))
+            .collect();
+        for result in results.iter_mut() {
+            result
+                .matches
+                .retain(|m| match self.rules.get(m.pattern_index) {
+                    Some(rule) => applies[m.pattern_index] && !rule.allows(&result.line),
+                    None => true,
+                });
+        }
+        results.retain(|result| !result.matches.is_empty());
+    }
+
+    /// Highest severity among the rules matched in some results.
+    pub fn max_severity(&self, results: &[SearchResult]) -> Option<Severity> {
+        results
+            .iter()
+            .flat_map(|result| &result.matches)
+            .filter_map(|m| self.get(m.pattern_index))
+            .map(|rule| rule.severity)
+            .max()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::search::{build_matcher, build_searcher, search_contents, Engine};
+
+    fn rules(source: &str) -> Arc<RuleSet> {
+        Arc::new(RuleSet::parse(Path::new("/repo/gg.toml"), source, Path::new("/repo")).unwrap())
+    }
+
+    fn search(rules: &Arc<RuleSet>, path: &str, contents: &str) -> Vec<SearchResult> {
+        let matcher = build_matcher(&rules.patterns(), Engine::Default, false)
+            .unwrap()
+            .with_rules(rules.clone());
+        search_contents(
+            PathBuf::from(path),
+            PathBuf::from(path),
+            contents.as_bytes(),
+            &matcher,
+            &mut build_searcher(false),
+            None,
+        )
+        .map(|file_results| file_results.results)
+        .unwrap()
+    }
+
+    #[test]
+    fn rules_matching_where_another_rule_did_are_reported() {
+        let rules = rules(
+            r#"
+            [[rule]]
+            id = "todo"
+            regex = "TODO"
+
+            [[rule]]
+            id = "todo-secret"
+            regex = "TODO: remove key"
+            severity = "error"
+            "#,
+        );
+        let results = search(&rules, "/repo/a.txt", "TODO: remove key abc\n");
+        assert_eq!(rules.max_severity(&results), Some(Severity::Error));
+    }
+
+    #[test]
+    fn rules_with_exclusions_only_apply_elsewhere() {
+        let rules = rules(
+            r#"
+            [[rule]]
+            id = "foo"
+            regex = "foo"
+            globs = ["!sub/"]
+            "#,
+        );
+        let rule = &rules.rules()[0];
+        assert!(rule.applies_to(Some(Path::new("/repo/a.txt"))));
+        assert!(!rule.applies_to(Some(Path::new("/repo/sub/a.txt"))));
+        assert!(!search(&rules, "/repo/a.txt", "foo\n").is_empty());
+        assert!(search(&rules, "/repo/sub/a.txt", "foo\n").is_empty());
+    }
+
+    #[test]
+    fn rules_with_globs_only_apply_to_the_matching_files() {
+        let rules = rules(
+            r#"
+            [[rule]]
+            id = "foo"
+            regex = "foo"
+            globs = ["*.rs", "!tests/"]
+            "#,
+        );
+        let rule = &rules.rules()[0];
+        assert!(rule.applies_to(Some(Path::new("/repo/src/a.rs"))));
+        assert!(!rule.applies_to(Some(Path::new("/repo/src/a.txt"))));
+        assert!(!rule.applies_to(Some(Path::new("/repo/tests/a.rs"))));
+        assert!(rule.applies_to(None));
+    }
+
+    #[test]
+    fn invalid_regexes_are_reported_with_their_rule() {
+        let rules = rules(
+            r#"
+            [[rule]]
+            id = "fine"
+            regex = 'a'
+            [[rule]]
+            id = "broken"
+            regex = 'b('
+            "#,
+        );
+        let build = |patterns: &[String]| build_matcher(patterns, Engine::Default, false);
+        let Err(err) = build(&rules.patterns()) else {
+            panic!("the regex of 'broken' is invalid");
+        };
+        let err = rules.regex_error(err, |regex| build(&[regex.to_string()]).map(drop));
+        assert!(err.to_string().contains("rule 'broken'"));
+    }
+}
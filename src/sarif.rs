@@ -0,0 +1,224 @@
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::git::Provenance;
+use crate::rules::RuleSet;
+use crate::search::FileResults;
+use crate::utils;
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const SARIF_VERSION: &str = "2.1.0";
+/// Base the URIs of files read from a git revision are relative to, i.e. the working tree.
+const REPO_ROOT_BASE_ID: &str = "REPOROOT";
+
+/// A SARIF 2.1.0 log with a single run, in which each pattern is a rule and each match a result.
+pub struct SarifLog {
+    rules: Vec<Value>,
+    /// pattern, level and message of the results of each rule
+    patterns: Vec<String>,
+    levels: Vec<String>,
+    messages: Vec<Option<String>>,
+    results: Vec<Value>,
+    properties: serde_json::Map<String, Value>,
+    /// revision the files come from, when searched in git's objects
+    provenance: Option<Provenance>,
+}
+
+impl SarifLog {
+    pub fn new(
+        patterns: &[String],
+        rule_set: Option<&RuleSet>,
+        provenance: Option<Provenance>,
+    ) -> SarifLog {
+        let (rules, levels, messages): (Vec<_>, _, _) = match rule_set {
+            Some(rule_set) => rule_set
+                .rules()
+                .iter()
+                .map(|rule| {
+                    let level = rule.severity.to_string();
+                    let regex = format!("matches /{}/", rule.regex);
+                    (
+                        json!({
+                            "id": rule.id,
+                            "shortDescription": {
+                                "text": rule.message.as_deref().unwrap_or(&regex),
+                            },
+                            "fullDescription": { "text": regex },
+                            "defaultConfiguration": { "level": level },
+                        }),
+                        level,
+                        rule.message.clone(),
+                    )
+                })
+                .collect(),
+            None => patterns
+                .iter()
+                .enumerate()
+                .map(|(index, pattern)| {
+                    (
+                        // patterns make poor ids, e.g. they change with every tweak
+                        json!({
+                            "id": format!("pattern-{index}"),
+                            "shortDescription": { "text": format!("matches /{pattern}/") },
+                        }),
+                        String::from("warning"),
+                        None,
+                    )
+                })
+                .collect(),
+        };
+        SarifLog {
+            rules,
+            patterns: rule_set.map_or_else(|| patterns.to_vec(), RuleSet::patterns),
+            levels,
+            messages,
+            results: Vec::new(),
+            properties: serde_json::Map::new(),
+            provenance,
+        }
+    }
+
+    /// Add the matches of a file, `path` being the path to report for it.
+    pub fn add(&mut self, file_results: &FileResults, path: &Path) {
+        let artifact_location = self.artifact_location(path);
+        for result in &file_results.results {
+            let snippet = result.line.trim_end_matches(['\r', '\n']);
+            for m in &result.matches {
+                // columns are one-based and counted in unicode code points, see `columnKind`
+                let (start_line, start_column) = result.position(m.start);
+                let (end_line, end_column) = result.position(m.end);
+                let rule_id = self
+                    .rules
+                    .get(m.pattern_index)
+                    .map_or(Value::Null, |rule| rule["id"].clone());
+                let message = match self.messages.get(m.pattern_index) {
+                    Some(Some(message)) => message.clone(),
+                    _ => format!(
+                        "`{}` matches /{}/",
+                        &result.line[m.start..m.end],
+                        self.patterns
+                            .get(m.pattern_index)
+                            .map_or("", String::as_str)
+                    ),
+                };
+                self.results.push(json!({
+                    "ruleId": rule_id,
+                    "ruleIndex": m.pattern_index,
+                    "level": self.levels.get(m.pattern_index).map_or("warning", String::as_str),
+                    "message": { "text": message },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": artifact_location,
+                            "region": {
+                                "startLine": start_line,
+                                "startColumn": start_column,
+                                "endLine": end_line,
+                                "endColumn": end_column,
+                                "snippet": { "text": snippet },
+                            },
+                        },
+                    }],
+                }));
+            }
+        }
+    }
+
+    /// Location of the file at `path`: a relative or `file` URI, or one relative to the
+    /// repository's working tree for files of a git revision, whose paths are `{revision}:{path}`.
+    fn artifact_location(&self, path: &Path) -> Value {
+        let path = path.to_string_lossy().replace('\\', "/");
+        let in_revision = self.provenance.as_ref().and_then(|provenance| {
+            path.strip_prefix(&provenance.revision)
+                .and_then(|path| path.strip_prefix(':'))
+        });
+        if let Some(path) = in_revision {
+            json!({ "uri": utils::percent_encode(path.as_bytes()), "uriBaseId": REPO_ROOT_BASE_ID })
+        } else if Path::new(&path).is_absolute() {
+            json!({ "uri": file_uri(&path) })
+        } else {
+            json!({ "uri": utils::percent_encode(path.as_bytes()) })
+        }
+    }
+
+    /// Attach additional data to the run, e.g. the search's statistics.
+    pub fn set_property(&mut self, key: &str, value: Value) {
+        self.properties.insert(key.to_string(), value);
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut run = json!({
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_HOMEPAGE"),
+                    "rules": self.rules,
+                },
+            },
+            "columnKind": "unicodeCodePoints",
+            "results": self.results,
+            "properties": self.properties,
+        });
+        if let Some(provenance) = &self.provenance {
+            let workdir = provenance.workdir.to_string_lossy().replace('\\', "/");
+            let workdir_uri = file_uri(&format!("{}/", workdir.trim_end_matches('/')));
+            let mut version_control = json!({
+                "repositoryUri": provenance.remote_url.clone().unwrap_or_else(|| workdir_uri.clone()),
+                "revisionTag": provenance.revision,
+                "mappedTo": { "uriBaseId": REPO_ROOT_BASE_ID },
+            });
+            if let Some(commit_id) = &provenance.commit_id {
+                version_control["revisionId"] = json!(commit_id);
+            }
+            run["versionControlProvenance"] = json!([version_control]);
+            // base URIs must end with a slash
+            run["originalUriBaseIds"] = json!({
+                REPO_ROOT_BASE_ID: { "uri": workdir_uri },
+            });
+        }
+        json!({
+            "$schema": SARIF_SCHEMA,
+            "version": SARIF_VERSION,
+            "runs": [run],
+        })
+    }
+}
+
+/// `file` URI of an absolute path using forward slashes.
+fn file_uri(path: &str) -> String {
+    // Windows' absolute paths, e.g. `C:/dir`, need a leading slash too
+    let slash = if path.starts_with('/') { "" } else { "/" };
+    format!("file://{slash}{}", utils::percent_encode(path.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn artifact_locations_are_uris() {
+        let log = SarifLog::new(&[String::from("a")], None, None);
+        assert_eq!(
+            log.artifact_location(Path::new("dir #1/a b.rs")),
+            json!({ "uri": "dir%20%231/a%20b.rs" })
+        );
+        assert_eq!(
+            log.artifact_location(Path::new("/tmp/a b.rs")),
+            json!({ "uri": "file:///tmp/a%20b.rs" })
+        );
+        let provenance = Provenance {
+            remote_url: None,
+            workdir: PathBuf::from("/repo"),
+            revision: String::from("HEAD~1"),
+            commit_id: None,
+        };
+        let log = SarifLog::new(&[String::from("a")], None, Some(provenance));
+        assert_eq!(
+            log.artifact_location(Path::new("HEAD~1:src/main.rs")),
+            json!({ "uri": "src/main.rs", "uriBaseId": REPO_ROOT_BASE_ID })
+        );
+    }
+}
@@ -1,14 +1,23 @@
 #![allow(clippy::module_name_repetitions)]
 use std::{fmt, io};
-use std::{path::PathBuf, slice::Iter};
+use std::{
+    path::{Path, PathBuf},
+    slice::Iter,
+    sync::Arc,
+};
 
 use grep::{
     matcher::{Match, Matcher},
     regex::{self, RegexMatcher, RegexMatcherBuilder},
-    searcher::{sinks::UTF8, Searcher, SearcherBuilder},
+    searcher::{sinks::Bytes, Searcher, SearcherBuilder, Sink, SinkFinish, SinkMatch},
 };
+use regex_automata::{meta, nfa::thompson::WhichCaptures, util::syntax, Anchored, Input};
 use serde::Serialize;
 
+use crate::rules::RuleSet;
+use crate::stats::Stats;
+use crate::GGError;
+
 #[derive(Debug, Serialize, Clone)]
 pub struct SearchResult {
     pub line_number: u64,
@@ -18,10 +27,81 @@ pub struct SearchResult {
     pub matches: Vec<MatchRange>,
 }
 
+impl SearchResult {
+    fn new(line_number: u64, line: String, matches: Vec<MatchRange>) -> SearchResult {
+        // multiline matches span several lines, trailing line terminators aside
+        let n_lines = line.trim_end_matches('\n').matches('\n').count() as u64;
+        SearchResult {
+            line_number,
+            line,
+            line_start: line_number,
+            line_end: line_number + n_lines,
+            matches,
+        }
+    }
+
+    /// Number of lines the result spans.
+    pub fn n_lines(&self) -> u64 {
+        self.line_end - self.line_start + 1
+    }
+
+    /// The lines of the result, each with the parts of the matches it holds.
+    pub fn lines(&self) -> impl Iterator<Item = ResultLine<'_>> {
+        let mut line_start = 0;
+        self.line
+            .split_inclusive('\n')
+            .zip(self.line_number..)
+            .map(move |(line, line_number)| {
+                let (start, end) = (line_start, line_start + line.len());
+                line_start = end;
+                // the parts of matches on line terminators aren't part of the line's
+                let content_end = start + line.trim_end_matches(['\r', '\n']).len();
+                let matches = self
+                    .matches
+                    .iter()
+                    .filter(|m| {
+                        (m.start < end && m.end > start)
+                            || (m.is_empty() && (start..end).contains(&m.start))
+                    })
+                    .map(|m| MatchRange {
+                        start: m.start.clamp(start, content_end) - start,
+                        end: m.end.clamp(start, content_end) - start,
+                        pattern_index: m.pattern_index,
+                    })
+                    .collect();
+                ResultLine {
+                    line_number,
+                    line,
+                    matches,
+                }
+            })
+    }
+
+    /// Line number and one-based column, in unicode code points, of a byte offset in the result.
+    pub fn position(&self, offset: usize) -> (u64, usize) {
+        let before = &self.line[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        (
+            self.line_number + before.matches('\n').count() as u64,
+            before[line_start..].chars().count() + 1,
+        )
+    }
+}
+
+/// One of the lines of a result, with match offsets relative to the line.
+#[derive(Debug, Serialize)]
+pub struct ResultLine<'a> {
+    pub line_number: u64,
+    pub line: &'a str,
+    pub matches: Vec<MatchRange>,
+}
+
 #[derive(Serialize, Debug, Clone)]
 pub struct MatchRange {
     pub start: usize,
     pub end: usize,
+    /// index of the pattern that produced this match
+    pub pattern_index: usize,
 }
 
 impl MatchRange {
@@ -33,7 +113,11 @@ impl MatchRange {
     #[inline]
     pub fn new(start: usize, end: usize) -> MatchRange {
         assert!(start <= end);
-        MatchRange { start, end }
+        MatchRange {
+            start,
+            end,
+            pattern_index: 0,
+        }
     }
 
     #[inline]
@@ -41,12 +125,22 @@ impl MatchRange {
         MatchRange::new(m.start(), m.end())
     }
 
+    /// Return a new match attributed to the pattern with the given index.
+    #[inline]
+    pub fn with_pattern_index(&self, pattern_index: usize) -> MatchRange {
+        MatchRange {
+            pattern_index,
+            ..*self
+        }
+    }
+
     /// Creates a zero width match at the given offset.
     #[inline]
     pub fn zero(offset: usize) -> MatchRange {
         MatchRange {
             start: offset,
             end: offset,
+            pattern_index: 0,
         }
     }
 
@@ -100,6 +194,7 @@ impl MatchRange {
         MatchRange {
             start: self.start.checked_add(amount).unwrap(),
             end: self.end.checked_add(amount).unwrap(),
+            pattern_index: self.pattern_index,
         }
     }
 
@@ -182,96 +277,572 @@ struct PartialSearchResult {
     pub m: MatchRange,
 }
 
+/// Like `grep::searcher::sinks::UTF8`, but also keeps track of the number of bytes searched and
+/// stops at matching lines that aren't valid UTF-8, which are found in binary data, instead of
+/// failing, the lines matched before it being kept.
+struct MatchSink<F> {
+    on_match: F,
+    bytes_searched: u64,
+    binary: bool,
+}
+
+impl<F> MatchSink<F>
+where
+    F: FnMut(u64, &str) -> Result<bool, io::Error>,
+{
+    fn new(on_match: F) -> MatchSink<F> {
+        MatchSink {
+            on_match,
+            bytes_searched: 0,
+            binary: false,
+        }
+    }
+}
+
+impl<F> Sink for MatchSink<F>
+where
+    F: FnMut(u64, &str) -> Result<bool, io::Error>,
+{
+    type Error = io::Error;
+
+    fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, io::Error> {
+        let Ok(line) = std::str::from_utf8(mat.bytes()) else {
+            self.binary = true;
+            self.bytes_searched = mat.absolute_byte_offset() + mat.bytes().len() as u64;
+            return Ok(false);
+        };
+        let line_number = mat
+            .line_number()
+            .ok_or_else(|| io::Error::other("line numbers not enabled"))?;
+        (self.on_match)(line_number, line)
+    }
+
+    fn finish(&mut self, _searcher: &Searcher, finish: &SinkFinish) -> Result<(), io::Error> {
+        // the count stops short of the line the search was stopped at
+        self.bytes_searched = self.bytes_searched.max(finish.byte_count());
+        Ok(())
+    }
+}
+
 pub fn search_file(
     path: PathBuf,
-    matcher: &RegexMatcher,
+    matcher: &PatternsMatcher,
     searcher: &mut Searcher,
+    stats: Option<&Stats>,
 ) -> Result<FileResults, io::Error> {
-    let mut partial_results: Vec<PartialSearchResult> = Vec::new();
+    search_haystack(path, None, matcher, searcher, stats)
+}
 
-    searcher.search_path(
-        matcher,
-        &path,
-        UTF8(|lnum, line| {
-            matcher.find_iter(line.as_bytes(), |m| {
-                partial_results.push(PartialSearchResult {
-                    line_number: lnum,
-                    line: line.to_string(),
-                    m: MatchRange::from_match(m),
-                });
-                true
-            })?;
-            Ok(true)
-        }),
-    )?;
+/// Like `search_file`, for contents read from elsewhere than the file at `path`, e.g. from a git
+/// object, the results being reported under `label`.
+pub fn search_contents(
+    label: PathBuf,
+    path: PathBuf,
+    contents: &[u8],
+    matcher: &PatternsMatcher,
+    searcher: &mut Searcher,
+    stats: Option<&Stats>,
+) -> Result<FileResults, io::Error> {
+    let mut file_results = search_haystack(path, Some(contents), matcher, searcher, stats)?;
+    file_results.path = label;
+    Ok(file_results)
+}
+
+fn search_haystack(
+    path: PathBuf,
+    contents: Option<&[u8]>,
+    matcher: &PatternsMatcher,
+    searcher: &mut Searcher,
+    stats: Option<&Stats>,
+) -> Result<FileResults, io::Error> {
+    let (partial_results, bytes_searched, binary) = match &matcher.matchers {
+        Matchers::Default(matchers) => {
+            find_in_file(&path, contents, matchers, matcher.each_pattern(), searcher)?
+        }
+        #[cfg(feature = "pcre2")]
+        Matchers::Pcre2(matchers) => {
+            find_in_file(&path, contents, matchers, matcher.each_pattern(), searcher)?
+        }
+    };
 
     if partial_results.is_empty() {
+        if let Some(stats) = stats {
+            stats.add_searched(bytes_searched, binary, 0, 0);
+        }
         return Ok(FileResults {
             path,
             results: Vec::new(),
         });
     }
-    let mut results = vec![SearchResult {
-        line_number: partial_results[0].line_number,
-        line: partial_results[0].line.clone(),
-        line_start: partial_results[0].line_number,
-        line_end: partial_results[0].line_number,
-        matches: vec![partial_results[0].m.clone()],
-    }];
-    for partial_result in &partial_results[1..] {
-        let last_result = results.last_mut().unwrap();
-        if last_result.line_number == partial_result.line_number {
-            last_result.matches.push(partial_result.m.clone());
-            last_result.line_end = partial_result.line_number;
-        } else {
-            results.push(SearchResult {
-                line_number: partial_result.line_number,
-                line: partial_result.line.clone(),
-                line_start: partial_result.line_number,
-                line_end: partial_result.line_number,
-                matches: vec![partial_result.m.clone()],
-            });
+    let mut results: Vec<SearchResult> = Vec::new();
+    for partial_result in partial_results {
+        match results.last_mut() {
+            Some(last_result) if last_result.line_number == partial_result.line_number => {
+                last_result.matches.push(partial_result.m);
+            }
+            _ => results.push(SearchResult::new(
+                partial_result.line_number,
+                partial_result.line,
+                vec![partial_result.m],
+            )),
         }
     }
 
+    matcher.retain_applicable(Some(&path), &mut results);
+    let haystack = contents.map_or(Haystack::File(&path), Haystack::Contents);
+    if !matcher.satisfies_query(&results, haystack, searcher)? {
+        results.clear();
+    }
+
+    if let Some(stats) = stats {
+        stats.add_searched(
+            bytes_searched,
+            binary,
+            results.iter().map(SearchResult::n_lines).sum(),
+            results
+                .iter()
+                .map(|result| result.matches.len() as u64)
+                .sum(),
+        );
+    }
     Ok(FileResults { path, results })
 }
 
-#[allow(clippy::similar_names)]
-pub fn search_reader(
-    reader: impl std::io::BufRead,
-    matcher: &RegexMatcher,
+/// The matches found in a file, or in `contents` when given, along with the number of bytes
+/// searched and whether the search stopped at binary data.
+fn find_in_file<M: Matcher>(
+    path: &Path,
+    contents: Option<&[u8]>,
+    matchers: &EngineMatchers<M>,
+    each_pattern: bool,
     searcher: &mut Searcher,
-) -> Result<Vec<SearchResult>, io::Error> {
-    let mut results = Vec::new();
-    let mut line_number = 0;
-    searcher.search_reader(
-        matcher,
-        reader,
-        UTF8(|lnum, line| {
-            line_number = lnum;
-            let mut matches = Vec::new();
-            matcher.find_iter(line.as_bytes(), |m| {
-                matches.push(MatchRange::from_match(m));
-                true
-            })?;
-            results.push(SearchResult {
+) -> Result<(Vec<PartialSearchResult>, u64, bool), io::Error> {
+    let mut partial_results: Vec<PartialSearchResult> = Vec::new();
+
+    let mut sink = MatchSink::new(|lnum, line| {
+        matchers.find_matches(line.as_bytes(), each_pattern, |m| {
+            partial_results.push(PartialSearchResult {
                 line_number: lnum,
                 line: line.to_string(),
-                line_start: lnum,
-                line_end: lnum,
-                matches,
+                m,
             });
-            Ok(true)
-        }),
-    )?;
-    Ok(results)
+        })?;
+        Ok(true)
+    });
+    match contents {
+        Some(contents) => searcher.search_slice(&matchers.combined, contents, &mut sink)?,
+        None => searcher.search_path(&matchers.combined, path, &mut sink)?,
+    }
+    let (bytes_searched, binary) = (sink.bytes_searched, sink.binary);
+    Ok((partial_results, bytes_searched, binary))
+}
+
+/// Search `reader`, handing each result over to `on_result` as soon as it's found, unless the
+/// file query needs the whole input first. `path` is the file the data comes from, if known.
+pub fn search_reader(
+    mut reader: impl std::io::BufRead,
+    path: Option<&Path>,
+    matcher: &PatternsMatcher,
+    searcher: &mut Searcher,
+    stats: Option<&Stats>,
+    mut on_result: impl FnMut(SearchResult) -> Result<(), io::Error>,
+) -> Result<(), io::Error> {
+    let (mut matched_lines, mut matches) = (0, 0);
+    let mut on_result = |result: SearchResult| {
+        matched_lines += result.n_lines();
+        matches += result.matches.len() as u64;
+        on_result(result)
+    };
+    let (bytes_searched, binary) = if matcher.query.is_some() {
+        // kept to look for the patterns that produced no results
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents)?;
+        let mut results = Vec::new();
+        let searched = matcher.find_in_reader(contents.as_slice(), searcher, |result| {
+            results.push(result);
+            Ok(())
+        })?;
+        matcher.retain_applicable(path, &mut results);
+        if matcher.satisfies_query(&results, Haystack::Contents(&contents), searcher)? {
+            results.into_iter().try_for_each(&mut on_result)?;
+        }
+        searched
+    } else {
+        matcher.find_in_reader(reader, searcher, |result| {
+            let mut results = vec![result];
+            matcher.retain_applicable(path, &mut results);
+            results.into_iter().try_for_each(&mut on_result)
+        })?
+    };
+    if let Some(stats) = stats {
+        stats.add_searched(bytes_searched, binary, matched_lines, matches);
+    }
+    Ok(())
 }
 
-pub fn build_matcher(patterns: &[String]) -> Result<RegexMatcher, regex::Error> {
-    let builder = RegexMatcherBuilder::new();
+/// Data that was searched, to search it again for single patterns.
+#[derive(Clone, Copy)]
+enum Haystack<'a> {
+    File(&'a Path),
+    Contents(&'a [u8]),
+}
+
+/// Whether `matcher` matches somewhere in `haystack`, as searched by `searcher`.
+fn occurs_in<M: Matcher>(
+    matcher: &M,
+    haystack: Haystack,
+    searcher: &mut Searcher,
+) -> Result<bool, io::Error> {
+    let mut found = false;
+    let sink = Bytes(|_, _| {
+        found = true;
+        Ok(false)
+    });
+    match haystack {
+        Haystack::File(path) => searcher.search_path(matcher, path, sink)?,
+        Haystack::Contents(contents) => searcher.search_slice(matcher, contents, sink)?,
+    }
+    Ok(found)
+}
+
+/// Search `reader`, handing each matching line over to `on_result`, and return the number of
+/// bytes searched and whether the search stopped at binary data.
+fn find_in_reader<M: Matcher>(
+    reader: impl std::io::BufRead,
+    matchers: &EngineMatchers<M>,
+    each_pattern: bool,
+    searcher: &mut Searcher,
+    mut on_result: impl FnMut(SearchResult) -> Result<(), io::Error>,
+) -> Result<(u64, bool), io::Error> {
+    let mut sink = MatchSink::new(|lnum, line| {
+        let mut matches = Vec::new();
+        matchers.find_matches(line.as_bytes(), each_pattern, |m| matches.push(m))?;
+        on_result(SearchResult::new(lnum, line.to_string(), matches))?;
+        Ok(true)
+    });
+    searcher.search_reader(&matchers.combined, reader, &mut sink)?;
+    Ok((sink.bytes_searched, sink.binary))
+}
+
+fn matcher_error(err: impl fmt::Display) -> io::Error {
+    io::Error::other(err.to_string())
+}
+
+/// Regex engine the patterns are compiled with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Engine {
+    /// Rust's regex engine, which guarantees searches in linear time
+    #[default]
+    Default,
+    /// PCRE2, which supports look-around and backreferences (needs the pcre2 feature)
+    Pcre2,
+    /// the default engine, falling back to PCRE2 when it rejects the patterns
+    Auto,
+}
+
+/// Searches for all patterns at once, while being able to tell which of them produced a match.
+pub struct PatternsMatcher {
+    matchers: Matchers,
+    /// rules the patterns come from, restricting where their matches are reported
+    rules: Option<Arc<RuleSet>>,
+    query: Option<FileQuery>,
+}
+
+enum Matchers {
+    Default(EngineMatchers<RegexMatcher>),
+    #[cfg(feature = "pcre2")]
+    Pcre2(EngineMatchers<grep::pcre2::RegexMatcher>),
+}
+
+/// The patterns compiled by one of the regex engines, `M` being its matcher type.
+struct EngineMatchers<M> {
+    combined: M,
+    /// all patterns in a single regex telling them apart, only compiled for the default engine
+    /// when there are several patterns
+    patterns: Option<meta::Regex>,
+    /// one matcher per pattern, only compiled when there are several of them
+    pattern_matchers: Vec<M>,
+}
+
+impl PatternsMatcher {
+    /// Only report the matches of each pattern where the rule it comes from applies.
+    pub fn with_rules(self, rules: Arc<RuleSet>) -> PatternsMatcher {
+        PatternsMatcher {
+            rules: Some(rules),
+            ..self
+        }
+    }
+
+    /// Only report the files satisfying `query`.
+    pub fn with_query(self, query: FileQuery) -> PatternsMatcher {
+        PatternsMatcher {
+            query: Some(query),
+            ..self
+        }
+    }
+
+    fn find_in_reader(
+        &self,
+        reader: impl std::io::BufRead,
+        searcher: &mut Searcher,
+        on_result: impl FnMut(SearchResult) -> Result<(), io::Error>,
+    ) -> Result<(u64, bool), io::Error> {
+        match &self.matchers {
+            Matchers::Default(matchers) => {
+                find_in_reader(reader, matchers, self.each_pattern(), searcher, on_result)
+            }
+            #[cfg(feature = "pcre2")]
+            Matchers::Pcre2(matchers) => {
+                find_in_reader(reader, matchers, self.each_pattern(), searcher, on_result)
+            }
+        }
+    }
+
+    /// Whether any of the patterns matches somewhere in `haystack`.
+    pub fn is_match(&self, haystack: &[u8]) -> bool {
+        match &self.matchers {
+            Matchers::Default(matchers) => matchers.combined.is_match(haystack).unwrap_or(false),
+            #[cfg(feature = "pcre2")]
+            Matchers::Pcre2(matchers) => matchers.combined.is_match(haystack).unwrap_or(false),
+        }
+    }
+
+    /// Whether the matches of every pattern are looked for, rather than those of the combined
+    /// matcher only. A rule matching where another one did must be reported too.
+    fn each_pattern(&self) -> bool {
+        self.rules.is_some()
+    }
+
+    /// Drop the matches of the rules that don't apply to `path`.
+    fn retain_applicable(&self, path: Option<&Path>, results: &mut Vec<SearchResult>) {
+        if let Some(rules) = &self.rules {
+            rules.retain_applicable(path, results);
+        }
+    }
+
+    /// Whether the results found in `haystack` are to be reported, as far as the query is
+    /// concerned. A pattern can match where another one did, without producing any result then,
+    /// so the patterns the query depends on and that produced none are looked for one at a time.
+    fn satisfies_query(
+        &self,
+        results: &[SearchResult],
+        haystack: Haystack,
+        searcher: &mut Searcher,
+    ) -> Result<bool, io::Error> {
+        let Some(query) = &self.query else {
+            return Ok(true);
+        };
+        if results.is_empty() {
+            return Ok(false);
+        }
+        let mut found = vec![false; self.n_patterns()];
+        for m in results.iter().flat_map(|result| &result.matches) {
+            found[m.pattern_index] = true;
+        }
+        for (index, found) in found.iter_mut().enumerate() {
+            let excluding = index >= query.n_patterns;
+            if *found || !(excluding || query.all_of) {
+                continue;
+            }
+            *found = match &self.matchers {
+                Matchers::Default(matchers) => {
+                    occurs_in(matchers.pattern_matcher(index), haystack, searcher)?
+                }
+                #[cfg(feature = "pcre2")]
+                Matchers::Pcre2(matchers) => {
+                    occurs_in(matchers.pattern_matcher(index), haystack, searcher)?
+                }
+            };
+            // the file is excluded, or misses one of the patterns it must hold
+            if *found == excluding {
+                break;
+            }
+        }
+        Ok(query.is_satisfied_by(&found))
+    }
+
+    fn n_patterns(&self) -> usize {
+        match &self.matchers {
+            Matchers::Default(matchers) => matchers.pattern_matchers.len().max(1),
+            #[cfg(feature = "pcre2")]
+            Matchers::Pcre2(matchers) => matchers.pattern_matchers.len().max(1),
+        }
+    }
+}
+
+impl<M: Matcher> EngineMatchers<M> {
+    /// Matcher of the pattern with the given index alone.
+    fn pattern_matcher(&self, index: usize) -> &M {
+        self.pattern_matchers.get(index).unwrap_or(&self.combined)
+    }
+
+    /// Find the matches in `haystack`, along with the pattern producing them. With `each_pattern`,
+    /// the matches of every pattern are looked for separately, so that matches overlapping the
+    /// ones of another pattern are found too.
+    fn find_matches(
+        &self,
+        haystack: &[u8],
+        each_pattern: bool,
+        mut on_match: impl FnMut(MatchRange),
+    ) -> Result<(), io::Error> {
+        if !each_pattern || self.pattern_matchers.is_empty() {
+            return self
+                .combined
+                .find_iter(haystack, |m| {
+                    on_match(
+                        MatchRange::from_match(m)
+                            .with_pattern_index(self.pattern_index(haystack, m)),
+                    );
+                    true
+                })
+                .map_err(matcher_error);
+        }
+        let mut matches = Vec::new();
+        for (index, matcher) in self.pattern_matchers.iter().enumerate() {
+            matcher
+                .find_iter(haystack, |m| {
+                    matches.push(MatchRange::from_match(m).with_pattern_index(index));
+                    true
+                })
+                .map_err(matcher_error)?;
+        }
+        matches.sort_by_key(|m| (m.start, m.pattern_index));
+        matches.into_iter().for_each(on_match);
+        Ok(())
+    }
+
+    /// Index of the pattern that produced `m`, a match of the combined matcher in `haystack`.
+    fn pattern_index(&self, haystack: &[u8], m: Match) -> usize {
+        if let Some(patterns) = &self.patterns {
+            // like the combined matcher, prefers the first of the patterns matching at m's start
+            let input = Input::new(haystack)
+                .range(m.start()..)
+                .anchored(Anchored::Yes);
+            if let Some(pm) = patterns.search(&input) {
+                return pm.pattern().as_usize();
+            }
+        }
+        // tried one pattern at a time when the regex above can't report a match, which happens
+        // when its DFA gives up, e.g. on unicode word boundaries next to non-ascii text
+        let matches_at = |matcher: &M| matcher.find_at(haystack, m.start()).ok().flatten();
+        self.pattern_matchers
+            .iter()
+            .position(|matcher| matches_at(matcher) == Some(m))
+            .or_else(|| {
+                self.pattern_matchers.iter().position(|matcher| {
+                    matches_at(matcher).is_some_and(|pm| pm.start() == m.start())
+                })
+            })
+            .unwrap_or(0)
+    }
+}
+
+/// Conditions on the patterns found in a file as a whole for its results to be reported.
+#[derive(Debug, Clone, Copy)]
+pub struct FileQuery {
+    /// number of patterns whose matches are reported, the ones following them excluding the
+    /// files they match
+    pub n_patterns: usize,
+    /// every reported pattern must match somewhere in the file
+    pub all_of: bool,
+}
+
+impl FileQuery {
+    /// Whether a file is reported, given which of the patterns are found in it.
+    fn is_satisfied_by(&self, found: &[bool]) -> bool {
+        let (reported, excluding) = found.split_at(self.n_patterns.min(found.len()));
+        !excluding.contains(&true) && (!self.all_of || !reported.contains(&false))
+    }
+}
+
+/// Compile `patterns` with `engine`, `.` matching line terminators too when `dotall` is set.
+pub fn build_matcher(
+    patterns: &[String],
+    engine: Engine,
+    dotall: bool,
+) -> Result<PatternsMatcher, GGError> {
+    let matchers = match engine {
+        Engine::Default => Matchers::Default(build_default_matchers(patterns, dotall)?),
+        Engine::Pcre2 => build_pcre2_matchers(patterns, dotall)?,
+        Engine::Auto => match build_default_matchers(patterns, dotall) {
+            Ok(matchers) => Matchers::Default(matchers),
+            // the default engine's error is the one to report when PCRE2 rejects the patterns too
+            Err(err) => build_pcre2_matchers(patterns, dotall).map_err(|_| err)?,
+        },
+    };
+    Ok(PatternsMatcher {
+        matchers,
+        rules: None,
+        query: None,
+    })
+}
+
+fn build_default_matchers(
+    patterns: &[String],
+    dotall: bool,
+) -> Result<EngineMatchers<RegexMatcher>, regex::Error> {
+    let mut builder = RegexMatcherBuilder::new();
+    // ^ and $ match at line boundaries when searching several lines at once
+    builder.multi_line(true).dot_matches_new_line(dotall);
     // matcher Error
-    builder.build_many(patterns)
+    let combined = builder.build_many(patterns)?;
+    if patterns.len() < 2 {
+        return Ok(EngineMatchers {
+            combined,
+            patterns: None,
+            pattern_matchers: Vec::new(),
+        });
+    }
+    // Capture states would make the memory used by the regex grow with the square of the number
+    // of patterns, which can be in the thousands. Without them, only its DFA reports matches.
+    let config = meta::Config::new()
+        .which_captures(WhichCaptures::None)
+        .nfa_size_limit(None);
+    let syntax = syntax::Config::new()
+        .multi_line(true)
+        .dot_matches_new_line(dotall);
+    Ok(EngineMatchers {
+        combined,
+        patterns: meta::Regex::builder()
+            .configure(config)
+            .syntax(syntax)
+            .build_many(patterns)
+            .ok(),
+        pattern_matchers: patterns
+            .iter()
+            .map(|pattern| builder.build(pattern))
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+#[cfg(feature = "pcre2")]
+fn build_pcre2_matchers(patterns: &[String], dotall: bool) -> Result<Matchers, GGError> {
+    let mut builder = grep::pcre2::RegexMatcherBuilder::new();
+    builder
+        .utf(true)
+        .ucp(true)
+        .jit_if_available(true)
+        .multi_line(true)
+        .dotall(dotall);
+    let combined = builder.build_many(patterns)?;
+    let pattern_matchers = if patterns.len() < 2 {
+        Vec::new()
+    } else {
+        patterns
+            .iter()
+            .map(|pattern| builder.build(pattern))
+            .collect::<Result<_, _>>()?
+    };
+    Ok(Matchers::Pcre2(EngineMatchers {
+        combined,
+        patterns: None,
+        pattern_matchers,
+    }))
+}
+
+#[cfg(not(feature = "pcre2"))]
+fn build_pcre2_matchers(_patterns: &[String], _dotall: bool) -> Result<Matchers, GGError> {
+    Err(GGError::Pcre2Unavailable)
 }
 
 pub fn build_searcher(multiline: bool) -> Searcher {
@@ -279,3 +850,97 @@ pub fn build_searcher(multiline: bool) -> Searcher {
     builder.multi_line(multiline);
     builder.build()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether a file holding `contents` is reported, checking that stdin agrees.
+    fn is_reported(patterns: &[&str], query: FileQuery, contents: &str) -> bool {
+        let patterns: Vec<String> = patterns.iter().map(ToString::to_string).collect();
+        let matcher = build_matcher(&patterns, Engine::Default, false)
+            .unwrap()
+            .with_query(query);
+        let mut searcher = build_searcher(false);
+        let file_results = search_contents(
+            PathBuf::from("a.txt"),
+            PathBuf::from("a.txt"),
+            contents.as_bytes(),
+            &matcher,
+            &mut searcher,
+            None,
+        )
+        .unwrap();
+        let mut stdin_results = Vec::new();
+        search_reader(
+            contents.as_bytes(),
+            None,
+            &matcher,
+            &mut searcher,
+            None,
+            |result| {
+                stdin_results.push(result);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(file_results.len(), stdin_results.len());
+        !file_results.is_empty()
+    }
+
+    #[test]
+    fn all_of_finds_patterns_overlapping_other_matches() {
+        let query = FileQuery {
+            n_patterns: 2,
+            all_of: true,
+        };
+        assert!(is_reported(&["foo", "foobar"], query, "foobar\n"));
+        assert!(is_reported(&["foobar", "foo"], query, "foobar\n"));
+        assert!(!is_reported(&["foo", "foobar"], query, "foo\nbar\n"));
+    }
+
+    #[test]
+    fn none_of_excludes_files_where_it_overlaps_other_matches() {
+        let query = FileQuery {
+            n_patterns: 1,
+            all_of: false,
+        };
+        assert!(!is_reported(&["foo", "foobar"], query, "foobar\n"));
+        assert!(!is_reported(&["foo", "foobar"], query, "foo\nfoobar\n"));
+        assert!(is_reported(&["foo", "foobar"], query, "foo\nbar\n"));
+    }
+
+    #[test]
+    fn matches_before_binary_data_are_kept() {
+        let matcher = build_matcher(&[String::from("foo")], Engine::Default, false).unwrap();
+        let mut searcher = build_searcher(false);
+        let contents = b"foo 1\nfoo \xff\nfoo 3\n";
+        let stats = Stats::default();
+        let file_results = search_contents(
+            PathBuf::from("a.bin"),
+            PathBuf::from("a.bin"),
+            contents,
+            &matcher,
+            &mut searcher,
+            Some(&stats),
+        )
+        .unwrap();
+        let mut stdin_results = Vec::new();
+        search_reader(
+            contents.as_slice(),
+            None,
+            &matcher,
+            &mut searcher,
+            Some(&stats),
+            |result| {
+                stdin_results.push(result);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(file_results.results.len(), 1);
+        assert_eq!(stdin_results.len(), 1);
+        let summary = stats.summary(std::time::Instant::now());
+        assert_eq!((summary.files_binary, summary.matches), (2, 2));
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+/// Counters describing a whole search, shared by all of the walker's threads.
+#[derive(Debug, Default)]
+pub struct Stats {
+    files_walked: AtomicU64,
+    files_searched: AtomicU64,
+    files_with_matches: AtomicU64,
+    files_binary: AtomicU64,
+    files_skipped_errors: AtomicU64,
+    entries_filtered: AtomicU64,
+    matched_lines: AtomicU64,
+    matches: AtomicU64,
+    bytes_searched: AtomicU64,
+}
+
+impl Stats {
+    pub fn add_walked(&self) {
+        self.files_walked.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_filtered(&self) {
+        self.entries_filtered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_error(&self) {
+        self.files_skipped_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a searched file, `binary` when the search stopped at binary data.
+    pub fn add_searched(
+        &self,
+        bytes_searched: u64,
+        binary: bool,
+        matched_lines: u64,
+        matches: u64,
+    ) {
+        self.files_searched.fetch_add(1, Ordering::Relaxed);
+        if binary {
+            self.files_binary.fetch_add(1, Ordering::Relaxed);
+        }
+        if matched_lines > 0 {
+            self.files_with_matches.fetch_add(1, Ordering::Relaxed);
+        }
+        self.matched_lines
+            .fetch_add(matched_lines, Ordering::Relaxed);
+        self.matches.fetch_add(matches, Ordering::Relaxed);
+        self.bytes_searched
+            .fetch_add(bytes_searched, Ordering::Relaxed);
+    }
+
+    pub fn summary(&self, started_at: Instant) -> StatsSummary {
+        StatsSummary {
+            files_walked: self.files_walked.load(Ordering::Relaxed),
+            files_searched: self.files_searched.load(Ordering::Relaxed),
+            files_with_matches: self.files_with_matches.load(Ordering::Relaxed),
+            files_binary: self.files_binary.load(Ordering::Relaxed),
+            files_skipped_errors: self.files_skipped_errors.load(Ordering::Relaxed),
+            entries_filtered: self.entries_filtered.load(Ordering::Relaxed),
+            matched_lines: self.matched_lines.load(Ordering::Relaxed),
+            matches: self.matches.load(Ordering::Relaxed),
+            bytes_searched: self.bytes_searched.load(Ordering::Relaxed),
+            wall_time: started_at.elapsed().as_secs_f64(),
+            cpu_time: cpu_time().map(|cpu_time| cpu_time.as_secs_f64()),
+        }
+    }
+}
+
+/// A snapshot of the search's statistics, printed once the search is over.
+#[derive(Debug, Serialize)]
+pub struct StatsSummary {
+    pub files_walked: u64,
+    pub files_searched: u64,
+    pub files_with_matches: u64,
+    /// searched files with a matching line that isn't valid UTF-8, as found in binary files,
+    /// where the search stopped, the matches of the lines before it being kept
+    pub files_binary: u64,
+    pub files_skipped_errors: u64,
+    /// entries excluded by `-I` and by the size and modification time filters while walking,
+    /// the walker skipping the ones of ignore files and file types without reporting them
+    pub entries_filtered: u64,
+    pub matched_lines: u64,
+    pub matches: u64,
+    pub bytes_searched: u64,
+    /// in seconds
+    pub wall_time: f64,
+    /// user and system time in seconds, when available on this platform
+    pub cpu_time: Option<f64>,
+}
+
+impl fmt::Display for StatsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} matches", self.matches)?;
+        writeln!(f, "{} matched lines", self.matched_lines)?;
+        writeln!(f, "{} files contained matches", self.files_with_matches)?;
+        writeln!(f, "{} files walked", self.files_walked)?;
+        writeln!(f, "{} files searched", self.files_searched)?;
+        writeln!(f, "{} files stopped at binary data", self.files_binary)?;
+        writeln!(f, "{} files skipped (errors)", self.files_skipped_errors)?;
+        writeln!(f, "{} entries filtered", self.entries_filtered)?;
+        writeln!(f, "{} bytes searched", self.bytes_searched)?;
+        write!(f, "{:.6} seconds spent searching", self.wall_time)?;
+        if let Some(cpu_time) = self.cpu_time {
+            write!(f, " ({cpu_time:.6} seconds of cpu time)")?;
+        }
+        writeln!(f)
+    }
+}
+
+#[cfg(unix)]
+fn cpu_time() -> Option<Duration> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: getrusage only writes to the provided struct, which is read after checking success.
+    let usage = unsafe {
+        if libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) != 0 {
+            return None;
+        }
+        usage.assume_init()
+    };
+    let to_duration = |time: libc::timeval| {
+        Duration::from_secs(u64::try_from(time.tv_sec).ok()?)
+            .checked_add(Duration::from_micros(u64::try_from(time.tv_usec).ok()?))
+    };
+    to_duration(usage.ru_utime)?.checked_add(to_duration(usage.ru_stime)?)
+}
+
+#[cfg(not(unix))]
+fn cpu_time() -> Option<Duration> {
+    None
+}
@@ -1,4 +1,7 @@
-use std::path::{Path, PathBuf};
+use std::{
+    io::{stdout, IsTerminal},
+    path::{Path, PathBuf},
+};
 
 pub fn resolve_paths(paths: Vec<PathBuf>) -> Vec<PathBuf> {
     paths.into_iter().map(|pb| resolve_path(&pb)).collect()
@@ -7,3 +10,115 @@ pub fn resolve_paths(paths: Vec<PathBuf>) -> Vec<PathBuf> {
 pub fn resolve_path(path: &Path) -> PathBuf {
     path.canonicalize().unwrap()
 }
+
+/// Width of the terminal stdout is attached to, if any.
+pub fn terminal_width() -> Option<usize> {
+    if !stdout().is_terminal() {
+        return None;
+    }
+    terminal_size::terminal_size().map(|(width, _)| usize::from(width.0))
+}
+
+/// Percent-encode a path for use in a URI, every byte but unreserved characters and `/` being
+/// escaped, e.g. so that `#` isn't taken for the start of a fragment.
+pub fn percent_encode(path: &[u8]) -> String {
+    let mut encoded = String::new();
+    for &byte in path {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+// Conversions between dates of the proleptic Gregorian calendar and days since the Unix epoch,
+// see http://howardhinnant.github.io/date_algorithms.html
+
+/// Days since 1970-01-01 of the date `year-month-day`.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Date as `(year, month, day)` that is `days` days after 1970-01-01, the inverse of
+/// `days_from_civil`.
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    (year_of_era + era * 400 + i64::from(month <= 2), month, day)
+}
+
+/// Number of days of a month, `None` for months out of 1..=12.
+pub fn days_in_month(year: i64, month: i64) -> Option<i64> {
+    let leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 => Some(28 + i64::from(leap_year)),
+        4 | 6 | 9 | 11 => Some(30),
+        1..=12 => Some(31),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn days_from_civil_counts_from_the_epoch() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_from_civil(2024, 2, 29), 19_782);
+        assert_eq!(days_from_civil(1900, 3, 1), -25_508);
+    }
+
+    #[test]
+    fn civil_from_days_is_the_inverse_of_days_from_civil() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+        // every day of about a thousand years around the epoch
+        let mut expected = civil_from_days(-200_000);
+        for days in -200_000..200_000 {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!((year, month, day), expected);
+            assert_eq!(days_from_civil(year, month, day), days);
+            expected = if Some(day) != days_in_month(year, month) {
+                (year, month, day + 1)
+            } else if month < 12 {
+                (year, month + 1, 1)
+            } else {
+                (year + 1, 1, 1)
+            };
+        }
+    }
+
+    #[test]
+    fn days_in_month_follows_the_leap_year_rules() {
+        assert_eq!(days_in_month(2023, 2), Some(28));
+        assert_eq!(days_in_month(2024, 2), Some(29));
+        assert_eq!(days_in_month(1900, 2), Some(28));
+        assert_eq!(days_in_month(2000, 2), Some(29));
+        assert_eq!(days_in_month(2024, 4), Some(30));
+        assert_eq!(days_in_month(2024, 12), Some(31));
+        assert_eq!(days_in_month(2024, 13), None);
+        assert_eq!(days_in_month(2024, 0), None);
+    }
+}